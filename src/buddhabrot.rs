@@ -0,0 +1,174 @@
+use iced_wgpu::{
+    core::Color,
+    wgpu::{self, ShaderStages},
+};
+
+use crate::{
+    params::{BuddhabrotParams, CoordinatesUniform, Viewport},
+    pipeline,
+    scene::Scene,
+    uniform::{Bindings, ShaderBuffer, Uniform},
+};
+
+// Invocations per workgroup, as declared by cs_main
+const WORKGROUP_SIZE: u32 = 64;
+// Samples of c added to the histogram every frame
+const SAMPLES_PER_FRAME: u32 = 1 << 16;
+// Frames of samples after which the histogram is left as it is, about 67
+// million samples
+const MAX_FRAMES: u32 = 1 << 10;
+
+/// Renderer of the Buddhabrot and the Nebulabrot. Every frame adds a batch
+/// of random orbits to a histogram of the view, which is drawn with the
+/// counts normalized by the largest one, so the image sharpens over frames
+/// until MAX_FRAMES batches are in. The histogram starts over when the view
+/// or the orbits counted change.
+pub struct Buddhabrot {
+    compute_pipeline: wgpu::ComputePipeline,
+    render_pipeline: wgpu::RenderPipeline,
+    params: Uniform<BuddhabrotParams>,
+    histogram: ShaderBuffer<u32>,
+    bindings: Bindings,
+    // View the histogram counts, None once it has to be cleared
+    view: Option<CoordinatesUniform>,
+    frame: u32,
+}
+
+impl Buddhabrot {
+    pub fn new(
+        device: &wgpu::Device,
+        texture_format: wgpu::TextureFormat,
+        viewport: &Uniform<Viewport>,
+        coordinates: &Uniform<CoordinatesUniform>,
+        params: BuddhabrotParams,
+    ) -> Self {
+        let params = Uniform::new("BuddhabrotParams", params, device);
+        let histogram = ShaderBuffer::new("Histogram", histogram_len(viewport), device);
+        let bindings = Bindings::new(
+            "Buddhabrot",
+            &[viewport, coordinates, &params, &histogram],
+            ShaderStages::FRAGMENT | ShaderStages::COMPUTE,
+            device,
+        );
+
+        let shader =
+            pipeline::shader_module(device, "Buddhabrot Shader", include_str!("buddhabrot.wgsl"));
+        let pipeline_layout = pipeline::pipeline_layout(device, &[&bindings]);
+
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Buddhabrot"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: Some("cs_main"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+
+        let render_pipeline = pipeline::render_pipeline(
+            device,
+            "Buddhabrot",
+            &pipeline_layout,
+            &shader,
+            ("vs_main", "fs_main"),
+            pipeline::replace_target(texture_format),
+            None,
+        );
+
+        Self {
+            compute_pipeline,
+            render_pipeline,
+            params,
+            histogram,
+            bindings,
+            view: None,
+            frame: 0,
+        }
+    }
+
+    /// Starts a histogram of the new size, bound with the resized viewport.
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        viewport: &Uniform<Viewport>,
+        coordinates: &Uniform<CoordinatesUniform>,
+    ) {
+        self.histogram = ShaderBuffer::new("Histogram", histogram_len(viewport), device);
+        self.bindings.rebind(
+            "Buddhabrot",
+            &[viewport, coordinates, &self.params, &self.histogram],
+            device,
+        );
+        self.view = None;
+    }
+
+    pub fn get_params(&self) -> BuddhabrotParams {
+        self.params.set_frame(0)
+    }
+
+    pub fn set_params(&mut self, params: BuddhabrotParams) {
+        if !self.params.has_same_orbits(&params) {
+            self.view = None;
+        }
+        self.params.set(params);
+    }
+
+    /// Whether the histogram of the current view has all its samples, so
+    /// drawing again won't change it.
+    pub fn is_complete(&self) -> bool {
+        self.view.is_some() && self.frame >= MAX_FRAMES
+    }
+
+    /// Adds a frame of samples to the histogram of `view`, which must be the
+    /// coordinates uploaded for this frame, unless it is complete, and draws
+    /// it.
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        target: &wgpu::TextureView,
+        background_color: Color,
+        view: CoordinatesUniform,
+    ) {
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        if self.view != Some(view) {
+            self.histogram.clear(&mut encoder);
+            self.view = Some(view);
+            self.frame = 0;
+        }
+
+        if self.frame < MAX_FRAMES {
+            self.params.set(self.params.set_frame(self.frame));
+            self.params.upload(queue);
+            self.frame += 1;
+
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: None,
+                timestamp_writes: None,
+            });
+
+            compute_pass.set_pipeline(&self.compute_pipeline);
+            compute_pass.set_bind_group(0, self.bindings.get_bind_group(), &[]);
+            compute_pass.dispatch_workgroups(SAMPLES_PER_FRAME / WORKGROUP_SIZE, 1, 1);
+        }
+
+        {
+            let mut render_pass =
+                Scene::begin_render_pass(target, &mut encoder, Some(background_color));
+
+            render_pass.set_pipeline(&self.render_pipeline);
+            render_pass.set_bind_group(0, self.bindings.get_bind_group(), &[]);
+            render_pass.draw(0..6, 0..1);
+        }
+
+        queue.submit([encoder.finish()]);
+    }
+}
+
+// Three counts per pixel, then the largest count of each channel
+fn histogram_len(viewport: &Viewport) -> usize {
+    let (width, height) = viewport.get_size();
+
+    (width * height + 1) * 3
+}
@@ -0,0 +1,235 @@
+// Buddhabrot: how often the escaping orbits of z^2 + c pass through each
+// pixel, accumulated over frames from random samples of c around the view.
+// The Nebulabrot counts each colour channel with its own iteration limit.
+
+struct Coordinates {
+    real: f32,
+    real_lo: f32,
+    imag: f32,
+    imag_lo: f32,
+    zoom: f32,
+    zoom_lo: f32,
+    zoom_mantissa: f32,
+    zoom_exponent: i32,
+    kernel: i32,
+};
+
+struct BuddhabrotParams {
+    red_iterations: i32,
+    green_iterations: i32,
+    blue_iterations: i32,
+    nebulabrot: i32,
+    normalization: i32,
+    gamma: f32,
+    frame: u32,
+};
+
+const NORMALIZATION_LINEAR: i32 = 0;
+const NORMALIZATION_LOGARITHMIC: i32 = 1;
+
+@group(0) @binding(0)
+var<uniform> viewport: Viewport;
+
+@group(0) @binding(1)
+var<uniform> coordinates: Coordinates;
+
+@group(0) @binding(2)
+var<uniform> params: BuddhabrotParams;
+
+// Three counts per pixel, one per channel, followed by the largest count of
+// each channel
+@group(0) @binding(3)
+var<storage, read_write> histogram: array<atomic<u32>>;
+
+// Half the side of the square holding the whole set
+const SET_RADIUS: f32 = 2.0;
+// Size of the window samples of c are drawn from, relative to the view
+const SAMPLE_MARGIN: f32 = 1.5;
+const BAILOUT: f32 = 4.0;
+
+fn width() -> u32 {
+    return u32(viewport.half_viewport_x * 2.0);
+}
+
+fn pixel_count() -> u32 {
+    return width() * u32(viewport.half_viewport_y * 2.0);
+}
+
+// PCG hash, enough randomness for picking samples
+fn hash(value: u32) -> u32 {
+    let state = value * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+
+    return (word >> 22u) ^ word;
+}
+
+// Uniform in [0, 1), advancing the seed
+fn random(seed: ptr<function, u32>) -> f32 {
+    *seed = hash(*seed);
+
+    return f32(*seed) / 4294967296.0;
+}
+
+fn channel_count() -> i32 {
+    return select(1, 3, params.nebulabrot > 0);
+}
+
+fn channel_iterations(channel: i32) -> i32 {
+    switch channel {
+        case 1: {
+            return params.green_iterations;
+        }
+        case 2: {
+            return params.blue_iterations;
+        }
+        default: {
+            return params.red_iterations;
+        }
+    }
+}
+
+fn max_iterations() -> i32 {
+    var iterations = 0;
+    for (var channel = 0; channel < channel_count(); channel++) {
+        iterations = max(iterations, channel_iterations(channel));
+    }
+
+    return iterations;
+}
+
+// Offset of z from the view center, subtracting the low parts of the center
+// after the high ones so the offset keeps them
+fn view_offset(z: vec2<f32>) -> vec2<f32> {
+    return vec2(
+        z.x - coordinates.real - coordinates.real_lo,
+        z.y - coordinates.imag - coordinates.imag_lo,
+    );
+}
+
+// Inverse of the shader's transform_position, -1 outside the view
+fn pixel_at(z: vec2<f32>) -> i32 {
+    let offset = view_offset(z) / coordinates.zoom;
+    let x = (offset.x / viewport.ratio + 1.0) * viewport.half_viewport_x;
+    let y = (1.0 - offset.y) * viewport.half_viewport_y;
+    if x < 0.0 || y < 0.0 || x >= viewport.half_viewport_x * 2.0
+        || y >= viewport.half_viewport_y * 2.0 {
+        return -1;
+    }
+
+    return i32(y) * i32(width()) + i32(x);
+}
+
+// Uniform sample of c over the view widened by SAMPLE_MARGIN, clipped to the
+// square holding the set, or over the whole square when the view is outside
+// it. Orbits from further away that cross the view are left out, which
+// keeps zoomed views from spending all their samples elsewhere.
+fn sample_c(seed: ptr<function, u32>) -> vec2<f32> {
+    let center = vec2(coordinates.real, coordinates.imag)
+        + vec2(coordinates.real_lo, coordinates.imag_lo);
+    let half_size = vec2(viewport.ratio, 1.0) * coordinates.zoom * SAMPLE_MARGIN;
+
+    var low = max(center - half_size, vec2(-SET_RADIUS));
+    var high = min(center + half_size, vec2(SET_RADIUS));
+    if any(low >= high) {
+        low = vec2(-SET_RADIUS);
+        high = vec2(SET_RADIUS);
+    }
+
+    let t = vec2(random(seed), random(seed));
+
+    return low + (high - low) * t;
+}
+
+// Main cardioid and period 2 bulb, whose orbits never escape
+fn is_interior(c: vec2<f32>) -> bool {
+    let shifted = c - vec2(0.25, 0.0);
+    let q = dot(shifted, shifted);
+    let bulb = c + vec2(1.0, 0.0);
+
+    return q * (q + shifted.x) <= 0.25 * c.y * c.y || dot(bulb, bulb) <= 0.0625;
+}
+
+fn mandelbrot_step(z: vec2<f32>, c: vec2<f32>) -> vec2<f32> {
+    return vec2(z.x * z.x - z.y * z.y, 2.0 * z.x * z.y) + c;
+}
+
+// Iteration the orbit of c escapes at, or -1 if it doesn't within limit
+fn escape_iteration(c: vec2<f32>, limit: i32) -> i32 {
+    var z = vec2(0.0);
+    for (var iteration = 1; iteration <= limit; iteration++) {
+        z = mandelbrot_step(z, c);
+        if dot(z, z) > BAILOUT {
+            return iteration;
+        }
+    }
+
+    return -1;
+}
+
+// One sample of c per invocation. Escaping orbits are traced a second time
+// to count the pixels they pass through, in every channel whose limit they
+// escape within.
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    var seed = hash(id.x ^ hash(params.frame));
+    let c = sample_c(&seed);
+    if is_interior(c) {
+        return;
+    }
+
+    let escape = escape_iteration(c, max_iterations());
+    if escape < 0 {
+        return;
+    }
+
+    let maxima = pixel_count() * 3u;
+    var z = vec2(0.0);
+    for (var iteration = 1; iteration < escape; iteration++) {
+        z = mandelbrot_step(z, c);
+        let pixel = pixel_at(z);
+        if pixel < 0 {
+            continue;
+        }
+
+        for (var channel = 0; channel < channel_count(); channel++) {
+            if escape <= channel_iterations(channel) {
+                let index = u32(pixel) * 3u + u32(channel);
+                let count = atomicAdd(&histogram[index], 1u) + 1u;
+                atomicMax(&histogram[maxima + u32(channel)], count);
+            }
+        }
+    }
+}
+
+// Count relative to the largest one of its channel, through the gamma
+fn brightness(count: u32, largest: u32) -> f32 {
+    if largest == 0u {
+        return 0.0;
+    }
+
+    var value = f32(count) / f32(largest);
+    if params.normalization == NORMALIZATION_LOGARITHMIC {
+        value = log(1.0 + f32(count)) / log(1.0 + f32(largest));
+    }
+
+    return pow(value, 1.0 / params.gamma);
+}
+
+@fragment
+fn fs_main(@builtin(position) in: vec4<f32>) -> @location(0) vec4<f32> {
+    let pixel = u32(in.y) * width() + u32(in.x);
+    let maxima = pixel_count() * 3u;
+
+    var color = vec3(0.0);
+    for (var channel = 0u; channel < u32(channel_count()); channel++) {
+        let count = atomicLoad(&histogram[pixel * 3u + channel]);
+        color[channel] = brightness(count, atomicLoad(&histogram[maxima + channel]));
+    }
+
+    // The Buddhabrot only counts in the red channel
+    if params.nebulabrot == 0 {
+        color = vec3(color.r);
+    }
+
+    return vec4(color, 1.0);
+}
@@ -0,0 +1,61 @@
+use std::f32::consts::FRAC_PI_2;
+
+use crate::params::Zoom;
+
+// Radians the camera turns per pixel dragged
+const ORBIT_SPEED: f32 = 0.01;
+// Short of straight up or down, where the camera's up vector flips
+const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+const DOLLY_FACTOR: f32 = 1.1;
+
+/// Camera of the 3D views, orbiting the origin with the y axis up. Dragging
+/// turns it and the wheel moves it closer or farther, within the distances
+/// the view allows.
+#[derive(Debug, Copy, Clone)]
+pub struct OrbitCamera {
+    yaw: f32,
+    pitch: f32,
+    distance: f32,
+    min_distance: f32,
+    max_distance: f32,
+}
+
+impl OrbitCamera {
+    pub fn new((yaw, pitch, distance): (f32, f32, f32), (min, max): (f32, f32)) -> Self {
+        Self {
+            yaw,
+            pitch,
+            distance: distance.clamp(min, max),
+            min_distance: min,
+            max_distance: max,
+        }
+    }
+
+    /// Yaw around the vertical axis and pitch above the horizon, in radians,
+    /// and the distance from the origin.
+    pub fn get(&self) -> (f32, f32, f32) {
+        (self.yaw, self.pitch, self.distance)
+    }
+
+    /// Turns the camera by the `motion` of the cursor, in pixels.
+    pub fn orbit(&mut self, motion: (f32, f32)) {
+        self.yaw += motion.0 * ORBIT_SPEED;
+        self.pitch = (self.pitch - motion.1 * ORBIT_SPEED).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    /// Keeps the camera at least `min` from the origin, moving it out if it
+    /// is closer.
+    pub fn set_min_distance(&mut self, min: f32) {
+        self.min_distance = min;
+        self.distance = self.distance.clamp(self.min_distance, self.max_distance);
+    }
+
+    /// Moves the camera toward or away from the origin.
+    pub fn dolly(&mut self, zoom: Zoom) {
+        let distance = match zoom {
+            Zoom::In => self.distance / DOLLY_FACTOR,
+            Zoom::Out => self.distance * DOLLY_FACTOR,
+        };
+        self.distance = distance.clamp(self.min_distance, self.max_distance);
+    }
+}
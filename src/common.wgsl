@@ -0,0 +1,70 @@
+// Prelude of every shader, put before its source by
+// pipeline::shader_module: the uniforms they share, the full screen vertex
+// shader and the HSL colours.
+
+struct Viewport {
+    half_viewport_x: f32,
+    half_viewport_y: f32,
+    ratio: f32,
+};
+
+struct ColorParams {
+    hue: f32,
+    saturation: f32,
+    lightness: f32,
+    hue_linked: i32,
+    saturation_linked: i32,
+    lightness_linked: i32,
+    smooth_color: i32,
+    coloring: i32,
+    interior_overlay: i32,
+    stripe_density: f32,
+    triangle_density: f32,
+};
+
+// Two triangles covering the view, for the shaders drawing every pixel
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_idx: u32) -> @builtin(position) vec4<f32> {
+    var pos = vec2<f32>(0.0, 0.0);
+    switch vertex_idx {
+        case 0u: {
+            pos.x = 1.0;
+            pos.y = 1.0;
+        }
+        case 1u, 5u: {
+            pos.x = -1.0;
+            pos.y = 1.0;
+        }
+        case 2u, 3u: {
+            pos.x = 1.0;
+            pos.y = -1.0;
+        }
+        case 4u: {
+            pos.x = -1.0;
+            pos.y = -1.0;
+        }
+        default: {}
+    }
+
+    return vec4<f32>(pos, 0.0, 1.0);
+}
+
+fn hsl_to_rgb(hsv: vec3<f32>) -> vec3<f32> {
+    let K = vec4(1.0, 2.0 / 3.0, 1.0 / 3.0, 3.0);
+    let p = abs(fract(hsv.xxx + K.xyz) * 6.0 - K.www);
+
+    let clamped = clamp(p - K.xxx, vec3(0.0), vec3(1.0));
+
+    return hsv.z * mix(K.xxx, clamped, hsv.y);
+}
+
+// Colour of a value in [0, 1] that the linked HSL components of params
+// follow. Values of 1 and above, like the iterations of pixels inside the
+// set, are black.
+fn hsl_color(value: f32, params: ColorParams) -> vec3<f32> {
+    let hue = select(params.hue, value + params.hue - 1.0, params.hue_linked > 0);
+    let saturation = select(params.saturation, params.saturation * value, params.saturation_linked > 0);
+    let lightness = select(0.0, select(params.lightness, params.lightness * value, params.lightness_linked > 0), value < 1.0);
+
+    return hsl_to_rgb(vec3(hue, saturation, lightness));
+}
@@ -1,26 +1,135 @@
-use std::num::ParseIntError;
+use std::num::{ParseFloatError, ParseIntError};
 
 use iced_wgpu::{
     Renderer,
     core::{
-        Color, Element, Length, Padding,
+        Color, Element, Length, Padding, Rectangle,
         alignment::{Horizontal, Vertical},
+        widget::{Id, Operation, operation::Focusable},
     },
 };
-use iced_widget::{Column, Row, Text, Theme, checkbox, container, slider, text_input};
+use iced_widget::{Column, Row, Text, Theme, checkbox, container, pick_list, slider, text_input};
 use iced_winit::runtime::Task;
 
+use crate::{
+    expression::Expression,
+    newton,
+    params::{
+        BailoutNorm, Coloring, Coordinates, Formula, HybridParams, LyapunovParams, MAX_BULB_POWER,
+        MAX_CONTOURS, MAX_EXPONENT, MAX_GAMMA, MAX_HEIGHT_SCALE, MIN_BULB_POWER, MIN_EXPONENT,
+        MIN_GAMMA, MIN_HEIGHT_SCALE, Normalization, TrapShape,
+    },
+};
+
+// Id of the container holding the controls, found by [`Panel`]
+const PANEL_ID: &str = "controls";
+
 pub struct Controls {
     pub color: (f32, f32, f32),
     pub color_linked: (bool, bool, bool),
+    pub smooth: bool,
+    pub coloring: Coloring,
+    pub interior_overlay: bool,
+    pub trap_shape: TrapShape,
+    pub trap_center: (f32, f32),
+    pub trap_size: f32,
+    pub stripe_density: f32,
+    pub triangle_density: f32,
+    pub lighting: bool,
+    pub light: (f32, f32, f32),
+    pub buddhabrot: bool,
+    // Whether the device can run the Buddhabrot's compute shader
+    pub buddhabrot_supported: bool,
+    pub nebulabrot: bool,
+    pub nebulabrot_iterations: [i32; 3],
+    pub normalization: Normalization,
+    pub gamma: f32,
+    pub mandelbulb: bool,
+    pub bulb_power: f32,
+    pub soft_shadows: bool,
+    pub ambient_occlusion: bool,
+    pub fog: f32,
+    pub terrain: bool,
+    pub height_scale: f32,
+    pub contours: u32,
     pub iterations: i32,
+    pub location: String,
+    pub goto: Option<Coordinates>,
+    pub location_error: Option<String>,
+    pub skipped_iterations: i32,
+    pub julia: bool,
+    pub julia_preview: bool,
+    pub julia_constant: (f64, f64),
+    pub exponent: f32,
+    pub exponent_committed: bool,
+    pub formula: Formula,
+    pub phoenix: (f32, f32),
+    pub relaxation: f32,
+    pub bailout_radius: f32,
+    pub bailout_norm: BailoutNorm,
+    pub polynomial: String,
+    pub polynomial_coefficients: bool,
+    pub newton_roots: Option<Vec<(f64, f64)>>,
+    pub polynomial_error: Option<String>,
+    pub custom_formula: String,
+    pub custom_expression: Option<Expression>,
+    pub custom_formula_error: Option<String>,
+    pub lyapunov_sequence: String,
+    pub lyapunov_params: Option<LyapunovParams>,
+    pub lyapunov_error: Option<String>,
+    pub hybrid_steps: String,
+    pub hybrid_params: Option<HybridParams>,
+    pub hybrid_error: Option<String>,
 }
 
 #[derive(Debug, Clone)]
 pub enum Message {
     ColorChanged(f32, f32, f32),
     ColorLinkChanged(bool, bool, bool),
+    SmoothToggled(bool),
+    ColoringChanged(Coloring),
+    InteriorOverlayToggled(bool),
+    TrapShapeChanged(TrapShape),
+    TrapCenterChanged(f32, f32),
+    TrapSizeChanged(f32),
+    StripeDensityChanged(f32),
+    TriangleDensityChanged(f32),
+    LightingToggled(bool),
+    LightChanged(f32, f32, f32),
+    BuddhabrotToggled(bool),
+    NebulabrotToggled(bool),
+    NebulabrotIterationsChange(usize, Result<i32, ParseIntError>),
+    NormalizationChanged(Normalization),
+    GammaChanged(f32),
+    MandelbulbToggled(bool),
+    BulbPowerChanged(f32),
+    SoftShadowsToggled(bool),
+    AmbientOcclusionToggled(bool),
+    FogChanged(f32),
+    TerrainToggled(bool),
+    HeightScaleChanged(f32),
+    ContoursChanged(u32),
     IterationsChange(Result<i32, ParseIntError>),
+    LocationChanged(String),
+    LocationSubmitted,
+    JuliaToggled(bool),
+    JuliaConstantChanged(f64, f64),
+    ExponentChanged(f32),
+    ExponentReleased,
+    FormulaChanged(Formula),
+    PhoenixChanged(f32, f32),
+    RelaxationChanged(f32),
+    BailoutRadiusChange(Result<f32, ParseFloatError>),
+    BailoutNormChanged(BailoutNorm),
+    PolynomialChanged(String),
+    PolynomialSubmitted,
+    CoefficientsToggled(bool),
+    CustomFormulaChanged(String),
+    CustomFormulaSubmitted,
+    LyapunovSequenceChanged(String),
+    LyapunovSequenceSubmitted,
+    HybridStepsChanged(String),
+    HybridStepsSubmitted,
 }
 
 impl Controls {
@@ -28,7 +137,58 @@ impl Controls {
         Controls {
             color: (1., 1., 1.),
             color_linked: (false, false, false),
+            smooth: false,
+            coloring: Coloring::Iterations,
+            interior_overlay: false,
+            trap_shape: TrapShape::Point,
+            trap_center: (0., 0.),
+            trap_size: 0.5,
+            stripe_density: 5.,
+            triangle_density: 1.,
+            lighting: false,
+            light: (45., 45., 1.),
+            buddhabrot: false,
+            buddhabrot_supported: true,
+            nebulabrot: false,
+            nebulabrot_iterations: [5000, 500, 50],
+            normalization: Normalization::Linear,
+            gamma: 2.,
+            mandelbulb: false,
+            bulb_power: 8.,
+            soft_shadows: true,
+            ambient_occlusion: true,
+            fog: 0.1,
+            terrain: false,
+            height_scale: 1.,
+            contours: 10,
             iterations: 1000,
+            location: Coordinates::default().to_string(),
+            goto: None,
+            location_error: None,
+            skipped_iterations: 0,
+            julia: false,
+            julia_preview: false,
+            julia_constant: (0., 0.),
+            exponent: 2.,
+            exponent_committed: false,
+            formula: Formula::Mandelbrot,
+            phoenix: (-0.5, 0.),
+            relaxation: 1.,
+            bailout_radius: 256.,
+            bailout_norm: BailoutNorm::Euclidean,
+            polynomial: "1, 0, 0, -1".to_string(),
+            polynomial_coefficients: true,
+            newton_roots: None,
+            polynomial_error: None,
+            custom_formula: "z = z*z + c".to_string(),
+            custom_expression: None,
+            custom_formula_error: None,
+            lyapunov_sequence: "AB".to_string(),
+            lyapunov_params: None,
+            lyapunov_error: None,
+            hybrid_steps: "2 Mandelbrot, Burning Ship".to_string(),
+            hybrid_params: None,
+            hybrid_error: None,
         }
     }
 
@@ -46,11 +206,186 @@ impl Controls {
             Message::ColorLinkChanged(hue_link, saturation_link, lightness_link) => {
                 self.color_linked = (hue_link, saturation_link, lightness_link);
             }
+            Message::SmoothToggled(smooth) => {
+                self.smooth = smooth;
+            }
+            Message::ColoringChanged(coloring) => {
+                self.coloring = coloring;
+            }
+            Message::InteriorOverlayToggled(interior_overlay) => {
+                self.interior_overlay = interior_overlay;
+            }
+            Message::TrapShapeChanged(trap_shape) => {
+                self.trap_shape = trap_shape;
+            }
+            Message::TrapCenterChanged(real, imag) => {
+                self.trap_center = (real, imag);
+            }
+            Message::TrapSizeChanged(trap_size) => {
+                self.trap_size = trap_size;
+            }
+            Message::StripeDensityChanged(stripe_density) => {
+                self.stripe_density = stripe_density;
+            }
+            Message::TriangleDensityChanged(triangle_density) => {
+                self.triangle_density = triangle_density;
+            }
+            Message::LightingToggled(lighting) => {
+                self.lighting = lighting;
+            }
+            Message::LightChanged(azimuth, elevation, intensity) => {
+                self.light = (azimuth, elevation, intensity);
+            }
+            Message::BuddhabrotToggled(buddhabrot) => {
+                self.buddhabrot = buddhabrot;
+                self.mandelbulb &= !buddhabrot;
+                self.terrain &= !buddhabrot;
+            }
+            Message::NebulabrotToggled(nebulabrot) => {
+                self.nebulabrot = nebulabrot;
+            }
+            Message::NebulabrotIterationsChange(channel, iterations) => {
+                if let Ok(iterations) = iterations {
+                    self.nebulabrot_iterations[channel] = iterations
+                }
+            }
+            Message::NormalizationChanged(normalization) => {
+                self.normalization = normalization;
+            }
+            Message::GammaChanged(gamma) => {
+                self.gamma = gamma;
+            }
+            Message::MandelbulbToggled(mandelbulb) => {
+                self.mandelbulb = mandelbulb;
+                self.buddhabrot &= !mandelbulb;
+                self.terrain &= !mandelbulb;
+            }
+            Message::BulbPowerChanged(bulb_power) => {
+                self.bulb_power = bulb_power;
+            }
+            Message::SoftShadowsToggled(soft_shadows) => {
+                self.soft_shadows = soft_shadows;
+            }
+            Message::AmbientOcclusionToggled(ambient_occlusion) => {
+                self.ambient_occlusion = ambient_occlusion;
+            }
+            Message::FogChanged(fog) => {
+                self.fog = fog;
+            }
+            Message::TerrainToggled(terrain) => {
+                self.terrain = terrain;
+                self.buddhabrot &= !terrain;
+                self.mandelbulb &= !terrain;
+            }
+            Message::HeightScaleChanged(height_scale) => {
+                self.height_scale = height_scale;
+            }
+            Message::ContoursChanged(contours) => {
+                self.contours = contours;
+            }
             Message::IterationsChange(iterations) => {
                 if let Ok(iterations) = iterations {
                     self.iterations = iterations
                 }
             }
+            Message::LocationChanged(location) => {
+                self.location = location;
+            }
+            Message::LocationSubmitted => match self.formula.parse_coordinates(&self.location) {
+                Ok(coordinates) => {
+                    self.goto = Some(coordinates);
+                    self.location_error = None;
+                }
+                Err(error) => {
+                    self.location_error = Some(error.to_string());
+                }
+            },
+            Message::JuliaToggled(julia) => {
+                self.julia = julia;
+            }
+            Message::JuliaConstantChanged(real, imag) => {
+                self.julia_constant = (real, imag);
+            }
+            Message::ExponentChanged(exponent) => {
+                self.exponent = exponent;
+            }
+            Message::ExponentReleased => {
+                self.exponent_committed = true;
+            }
+            Message::FormulaChanged(formula) => {
+                self.formula = formula;
+                self.julia &= formula.has_julia();
+            }
+            Message::PhoenixChanged(real, imag) => {
+                self.phoenix = (real, imag);
+            }
+            Message::RelaxationChanged(relaxation) => {
+                self.relaxation = relaxation;
+            }
+            Message::BailoutRadiusChange(bailout_radius) => {
+                if let Ok(bailout_radius) = bailout_radius
+                    && bailout_radius > 0.
+                {
+                    self.bailout_radius = bailout_radius
+                }
+            }
+            Message::BailoutNormChanged(bailout_norm) => {
+                self.bailout_norm = bailout_norm;
+            }
+            Message::PolynomialChanged(polynomial) => {
+                self.polynomial = polynomial;
+            }
+            Message::PolynomialSubmitted => {
+                match newton::parse_roots(&self.polynomial, self.polynomial_coefficients) {
+                    Ok(roots) => {
+                        self.newton_roots = Some(roots);
+                        self.polynomial_error = None;
+                    }
+                    Err(error) => {
+                        self.polynomial_error = Some(error.to_string());
+                    }
+                }
+            }
+            Message::CoefficientsToggled(coefficients) => {
+                self.polynomial_coefficients = coefficients;
+                return self.update(Message::PolynomialSubmitted);
+            }
+            Message::CustomFormulaChanged(custom_formula) => {
+                self.custom_formula = custom_formula;
+            }
+            Message::CustomFormulaSubmitted => match self.custom_formula.parse() {
+                Ok(expression) => {
+                    self.custom_expression = Some(expression);
+                    self.custom_formula_error = None;
+                }
+                Err(error) => {
+                    self.custom_formula_error = Some(error.to_string());
+                }
+            },
+            Message::LyapunovSequenceChanged(lyapunov_sequence) => {
+                self.lyapunov_sequence = lyapunov_sequence;
+            }
+            Message::LyapunovSequenceSubmitted => match self.lyapunov_sequence.parse() {
+                Ok(lyapunov_params) => {
+                    self.lyapunov_params = Some(lyapunov_params);
+                    self.lyapunov_error = None;
+                }
+                Err(error) => {
+                    self.lyapunov_error = Some(error.to_string());
+                }
+            },
+            Message::HybridStepsChanged(hybrid_steps) => {
+                self.hybrid_steps = hybrid_steps;
+            }
+            Message::HybridStepsSubmitted => match self.hybrid_steps.parse() {
+                Ok(hybrid_params) => {
+                    self.hybrid_params = Some(hybrid_params);
+                    self.hybrid_error = None;
+                }
+                Err(error) => {
+                    self.hybrid_error = Some(error.to_string());
+                }
+            },
         }
 
         Task::none()
@@ -59,7 +394,51 @@ impl Controls {
     pub fn view(&self) -> Element<'_, Message, Theme, Renderer> {
         let color = self.color;
         let color_linked = self.color_linked;
+        let smooth = self.smooth;
+        let coloring = self.coloring;
+        let interior_overlay = self.interior_overlay;
+        let trap_shape = self.trap_shape;
+        let (trap_real, trap_imag) = self.trap_center;
+        let trap_size = self.trap_size;
+        let stripe_density = self.stripe_density;
+        let triangle_density = self.triangle_density;
+        let lighting = self.lighting;
+        let light = self.light;
+        let buddhabrot = self.buddhabrot;
+        let buddhabrot_supported = self.buddhabrot_supported;
+        let nebulabrot = self.nebulabrot;
+        let nebulabrot_iterations = self.nebulabrot_iterations;
+        let normalization = self.normalization;
+        let gamma = self.gamma;
+        let mandelbulb = self.mandelbulb;
+        let bulb_power = self.bulb_power;
+        let soft_shadows = self.soft_shadows;
+        let ambient_occlusion = self.ambient_occlusion;
+        let fog = self.fog;
+        let terrain = self.terrain;
+        let height_scale = self.height_scale;
+        let contours = self.contours;
         let iterations = self.iterations;
+        let location = &self.location;
+        let location_error = &self.location_error;
+        let skipped_iterations = self.skipped_iterations;
+        let julia = self.julia;
+        let (julia_real, julia_imag) = self.julia_constant;
+        let exponent = self.exponent;
+        let formula = self.formula;
+        let (phoenix_real, phoenix_imag) = self.phoenix;
+        let relaxation = self.relaxation;
+        let bailout_radius = self.bailout_radius;
+        let bailout_norm = self.bailout_norm;
+        let polynomial = &self.polynomial;
+        let polynomial_coefficients = self.polynomial_coefficients;
+        let polynomial_error = &self.polynomial_error;
+        let custom_formula = &self.custom_formula;
+        let custom_formula_error = &self.custom_formula_error;
+        let lyapunov_sequence = &self.lyapunov_sequence;
+        let lyapunov_error = &self.lyapunov_error;
+        let hybrid_steps = &self.hybrid_steps;
+        let hybrid_error = &self.hybrid_error;
 
         let controls = Row::new()
             .width(600)
@@ -117,11 +496,324 @@ impl Controls {
                         };
                         Message::IterationsChange(parsed)
                     }))
+                    .push(
+                        Text::new(format!("Skipped: {skipped_iterations}"))
+                            .size(12)
+                            .color(Color::WHITE),
+                    )
+                    .push(checkbox("Smooth", smooth).on_toggle(Message::SmoothToggled))
+                    .push(pick_list(
+                        &Coloring::ALL[..],
+                        Some(coloring),
+                        Message::ColoringChanged,
+                    ))
+                    .push(
+                        checkbox("Interior tests", interior_overlay)
+                            .on_toggle(Message::InteriorOverlayToggled),
+                    )
                     .width(Length::Fill),
             );
 
+        let controls = Column::new()
+            .width(600)
+            .spacing(10)
+            .push(controls)
+            .push(
+                Column::new()
+                    .push(Text::new("Location").color(Color::WHITE))
+                    .push(
+                        text_input("real imag zoom", location)
+                            .on_input(Message::LocationChanged)
+                            .on_submit(Message::LocationSubmitted),
+                    )
+                    .push(error_text(location_error)),
+            )
+            .push((coloring == Coloring::OrbitTrap).then(|| {
+                Row::new()
+                    .spacing(20)
+                    .push(pick_list(
+                        &TrapShape::ALL[..],
+                        Some(trap_shape),
+                        Message::TrapShapeChanged,
+                    ))
+                    .push(
+                        Text::new(format!("Trap {trap_real:.2} {trap_imag:+.2}i"))
+                            .color(Color::WHITE),
+                    )
+                    .push(
+                        slider(-2.0..=2.0, trap_real, move |real| {
+                            Message::TrapCenterChanged(real, trap_imag)
+                        })
+                        .step(0.01),
+                    )
+                    .push(
+                        slider(-2.0..=2.0, trap_imag, move |imag| {
+                            Message::TrapCenterChanged(trap_real, imag)
+                        })
+                        .step(0.01),
+                    )
+                    .push(Text::new(format!("Size {trap_size:.2}")).color(Color::WHITE))
+                    .push(slider(0.01..=2.0, trap_size, Message::TrapSizeChanged).step(0.01))
+            }))
+            .push((coloring == Coloring::StripeAverage).then(|| {
+                Row::new()
+                    .spacing(20)
+                    .push(
+                        Text::new(format!("Stripe density {stripe_density:.0}"))
+                            .color(Color::WHITE),
+                    )
+                    .push(
+                        slider(1.0..=20.0, stripe_density, Message::StripeDensityChanged).step(1.0),
+                    )
+            }))
+            .push((coloring == Coloring::TriangleInequality).then(|| {
+                Row::new()
+                    .spacing(20)
+                    .push(Text::new(format!("Density {triangle_density:.1}")).color(Color::WHITE))
+                    .push(
+                        slider(
+                            1.0..=20.0,
+                            triangle_density,
+                            Message::TriangleDensityChanged,
+                        )
+                        .step(0.5),
+                    )
+            }))
+            .push(
+                Row::new()
+                    .spacing(20)
+                    .push(checkbox("Lighting", lighting).on_toggle(Message::LightingToggled))
+                    .push(lighting.then(|| {
+                        Row::new()
+                            .spacing(20)
+                            .push(Text::new(format!("Azimuth {:.0}", light.0)).color(Color::WHITE))
+                            .push(
+                                slider(0.0..=360.0, light.0, move |azimuth| {
+                                    Message::LightChanged(azimuth, light.1, light.2)
+                                })
+                                .step(1.0),
+                            )
+                            .push(
+                                Text::new(format!("Elevation {:.0}", light.1)).color(Color::WHITE),
+                            )
+                            .push(
+                                slider(0.0..=90.0, light.1, move |elevation| {
+                                    Message::LightChanged(light.0, elevation, light.2)
+                                })
+                                .step(1.0),
+                            )
+                            .push(
+                                Text::new(format!("Intensity {:.2}", light.2)).color(Color::WHITE),
+                            )
+                            .push(
+                                slider(0.0..=1.0, light.2, move |intensity| {
+                                    Message::LightChanged(light.0, light.1, intensity)
+                                })
+                                .step(0.01),
+                            )
+                    })),
+            )
+            .push(
+                Row::new()
+                    .spacing(20)
+                    .push(pick_list(
+                        &Formula::ALL[..],
+                        Some(formula),
+                        Message::FormulaChanged,
+                    ))
+                    .push(
+                        checkbox("Julia", julia)
+                            .on_toggle_maybe(formula.has_julia().then_some(Message::JuliaToggled)),
+                    )
+                    .push(Text::new(format!("Exponent {exponent:.2}")).color(Color::WHITE))
+                    .push(
+                        slider(
+                            MIN_EXPONENT..=MAX_EXPONENT,
+                            exponent,
+                            Message::ExponentChanged,
+                        )
+                        .step(0.05)
+                        .on_release(Message::ExponentReleased),
+                    )
+                    .push(
+                        Text::new(format!("c = {julia_real} {julia_imag:+}i")).color(Color::WHITE),
+                    )
+                    .push(checkbox("Buddhabrot", buddhabrot).on_toggle_maybe(
+                        buddhabrot_supported.then_some(Message::BuddhabrotToggled),
+                    ))
+                    .push(checkbox("Mandelbulb", mandelbulb).on_toggle(Message::MandelbulbToggled))
+                    .push(checkbox("Terrain", terrain).on_toggle(Message::TerrainToggled)),
+            )
+            .push(mandelbulb.then(|| {
+                Row::new()
+                    .spacing(20)
+                    .push(Text::new(format!("Power {bulb_power:.1}")).color(Color::WHITE))
+                    .push(
+                        slider(
+                            MIN_BULB_POWER..=MAX_BULB_POWER,
+                            bulb_power,
+                            Message::BulbPowerChanged,
+                        )
+                        .step(0.1),
+                    )
+                    .push(checkbox("Shadows", soft_shadows).on_toggle(Message::SoftShadowsToggled))
+                    .push(
+                        checkbox("Occlusion", ambient_occlusion)
+                            .on_toggle(Message::AmbientOcclusionToggled),
+                    )
+                    .push(Text::new(format!("Fog {fog:.2}")).color(Color::WHITE))
+                    .push(slider(0.0..=1.0, fog, Message::FogChanged).step(0.01))
+            }))
+            .push(terrain.then(|| {
+                Row::new()
+                    .spacing(20)
+                    .push(Text::new(format!("Height {height_scale:.1}")).color(Color::WHITE))
+                    .push(
+                        slider(
+                            MIN_HEIGHT_SCALE..=MAX_HEIGHT_SCALE,
+                            height_scale,
+                            Message::HeightScaleChanged,
+                        )
+                        .step(0.1),
+                    )
+                    .push(Text::new(format!("Contours {contours}")).color(Color::WHITE))
+                    .push(slider(0..=MAX_CONTOURS, contours, Message::ContoursChanged))
+                    .push(Text::new("Shift to move the view").color(Color::WHITE))
+            }))
+            .push(formula.has_bailout_radius().then(|| {
+                Row::new()
+                    .spacing(20)
+                    .push(Text::new("Bailout").color(Color::WHITE))
+                    .push(
+                        text_input("", &bailout_radius.to_string())
+                            .on_input(|v| Message::BailoutRadiusChange(v.parse::<f32>())),
+                    )
+                    .push(pick_list(
+                        &BailoutNorm::ALL[..],
+                        Some(bailout_norm),
+                        Message::BailoutNormChanged,
+                    ))
+            }))
+            .push(buddhabrot.then(|| {
+                Row::new()
+                    .spacing(20)
+                    .push(checkbox("Nebulabrot", nebulabrot).on_toggle(Message::NebulabrotToggled))
+                    .push(nebulabrot.then(|| {
+                        ["R", "G", "B"].into_iter().enumerate().fold(
+                            Row::new().spacing(10),
+                            |row, (channel, name)| {
+                                row.push(Text::new(name).color(Color::WHITE)).push(
+                                    text_input("", &nebulabrot_iterations[channel].to_string())
+                                        .on_input(move |v| {
+                                            let parsed = if v.is_empty() {
+                                                Ok(0)
+                                            } else {
+                                                v.parse::<i32>()
+                                            };
+                                            Message::NebulabrotIterationsChange(channel, parsed)
+                                        }),
+                                )
+                            },
+                        )
+                    }))
+                    .push(pick_list(
+                        &Normalization::ALL[..],
+                        Some(normalization),
+                        Message::NormalizationChanged,
+                    ))
+                    .push(Text::new(format!("Gamma {gamma:.1}")).color(Color::WHITE))
+                    .push(slider(MIN_GAMMA..=MAX_GAMMA, gamma, Message::GammaChanged).step(0.1))
+            }))
+            .push((formula == Formula::Newton).then(|| {
+                Column::new()
+                    .push(
+                        Row::new()
+                            .spacing(20)
+                            .push(Text::new("Polynomial").color(Color::WHITE))
+                            .push(
+                                text_input("real imag, real imag, ...", polynomial)
+                                    .on_input(Message::PolynomialChanged)
+                                    .on_submit(Message::PolynomialSubmitted),
+                            )
+                            .push(
+                                checkbox("Coefficients", polynomial_coefficients)
+                                    .on_toggle(Message::CoefficientsToggled),
+                            ),
+                    )
+                    .push(error_text(polynomial_error))
+            }))
+            .push((formula == Formula::Custom).then(|| {
+                Column::new()
+                    .push(
+                        Row::new()
+                            .spacing(20)
+                            .push(Text::new("Formula").color(Color::WHITE))
+                            .push(
+                                text_input("z = z*z + c", custom_formula)
+                                    .on_input(Message::CustomFormulaChanged)
+                                    .on_submit(Message::CustomFormulaSubmitted),
+                            ),
+                    )
+                    .push(error_text(custom_formula_error))
+            }))
+            .push((formula == Formula::Phoenix).then(|| {
+                Row::new()
+                    .spacing(20)
+                    .push(
+                        Text::new(format!("p = {phoenix_real:.2} {phoenix_imag:+.2}i"))
+                            .color(Color::WHITE),
+                    )
+                    .push(
+                        slider(-2.0..=2.0, phoenix_real, move |real| {
+                            Message::PhoenixChanged(real, phoenix_imag)
+                        })
+                        .step(0.01),
+                    )
+                    .push(
+                        slider(-2.0..=2.0, phoenix_imag, move |imag| {
+                            Message::PhoenixChanged(phoenix_real, imag)
+                        })
+                        .step(0.01),
+                    )
+            }))
+            .push((formula == Formula::Nova).then(|| {
+                Row::new()
+                    .spacing(20)
+                    .push(Text::new(format!("Relaxation {relaxation:.2}")).color(Color::WHITE))
+                    .push(slider(0.1..=2.0, relaxation, Message::RelaxationChanged).step(0.01))
+            }))
+            .push((formula == Formula::Lyapunov).then(|| {
+                Column::new()
+                    .push(
+                        Row::new()
+                            .spacing(20)
+                            .push(Text::new("Sequence").color(Color::WHITE))
+                            .push(
+                                text_input("AABAB", lyapunov_sequence)
+                                    .on_input(Message::LyapunovSequenceChanged)
+                                    .on_submit(Message::LyapunovSequenceSubmitted),
+                            ),
+                    )
+                    .push(error_text(lyapunov_error))
+            }))
+            .push((formula == Formula::Hybrid).then(|| {
+                Column::new()
+                    .push(
+                        Row::new()
+                            .spacing(20)
+                            .push(Text::new("Steps").color(Color::WHITE))
+                            .push(
+                                text_input("2 Mandelbrot, Burning Ship", hybrid_steps)
+                                    .on_input(Message::HybridStepsChanged)
+                                    .on_submit(Message::HybridStepsSubmitted),
+                            ),
+                    )
+                    .push(error_text(hybrid_error))
+            }));
+
         container(
             container(controls)
+                .id(PANEL_ID)
                 .height(Length::Shrink)
                 .width(Length::Fill)
                 .align_y(Vertical::Center)
@@ -134,3 +826,38 @@ impl Controls {
         .into()
     }
 }
+
+/// Bounds of the controls and whether one of their text inputs has focus,
+/// gathered by operating on the interface. Clicks on the panel and keys typed
+/// into it aren't meant for the view.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Panel {
+    pub bounds: Rectangle,
+    pub typing: bool,
+}
+
+impl Operation for Panel {
+    fn traverse(&mut self, operate: &mut dyn FnMut(&mut dyn Operation)) {
+        operate(self);
+    }
+
+    fn container(&mut self, id: Option<&Id>, bounds: Rectangle) {
+        if id == Some(&Id::new(PANEL_ID)) {
+            self.bounds = bounds;
+        }
+    }
+
+    fn focusable(&mut self, _id: Option<&Id>, _bounds: Rectangle, state: &mut dyn Focusable) {
+        self.typing |= state.is_focused();
+    }
+}
+
+/// Parse error of a text input, shown under it until the input is submitted
+/// again.
+fn error_text(error: &Option<String>) -> Option<Text<'_, Theme, Renderer>> {
+    error.as_ref().map(|error| {
+        Text::new(error)
+            .size(12)
+            .color(Color::from_rgb(1., 0.4, 0.4))
+    })
+}
@@ -0,0 +1,126 @@
+use std::{error::Error, fmt};
+
+use num_bigint::BigInt;
+use num_traits::Signed;
+
+// Powers of ten past this are rejected before any bignum work, which would
+// otherwise grow with them. The deepest zooms print about 1000 decimals.
+const MAX_DECIMAL_EXPONENT: i64 = 4000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseDecimalError;
+
+impl fmt::Display for ParseDecimalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid decimal number")
+    }
+}
+
+impl Error for ParseDecimalError {}
+
+/// Splits a decimal like `-12.5e-3` into its digits as an integer and the
+/// power of ten they are scaled by, here `-125` and `-4`. Both the exponent
+/// and the number of digits are bounded by [`MAX_DECIMAL_EXPONENT`].
+pub fn parse(s: &str) -> Result<(BigInt, i64), ParseDecimalError> {
+    let s = s.trim();
+    let (number, exponent) = match s.find(['e', 'E']) {
+        Some(index) => (
+            &s[..index],
+            s[index + 1..]
+                .parse::<i64>()
+                .map_err(|_| ParseDecimalError)?,
+        ),
+        None => (s, 0),
+    };
+
+    let (negative, number) = match number.strip_prefix('-') {
+        Some(number) => (true, number),
+        None => (false, number.strip_prefix('+').unwrap_or(number)),
+    };
+
+    let (integer, fraction) = number.split_once('.').unwrap_or((number, ""));
+    if !(-MAX_DECIMAL_EXPONENT..=MAX_DECIMAL_EXPONENT).contains(&exponent)
+        || integer.len() + fraction.len() > MAX_DECIMAL_EXPONENT as usize
+        || integer.is_empty() && fraction.is_empty()
+        || !integer
+            .bytes()
+            .chain(fraction.bytes())
+            .all(|b| b.is_ascii_digit())
+    {
+        return Err(ParseDecimalError);
+    }
+
+    let digits = format!("{integer}{fraction}")
+        .parse::<BigInt>()
+        .map_err(|_| ParseDecimalError)?;
+
+    Ok((
+        if negative { -digits } else { digits },
+        exponent - fraction.len() as i64,
+    ))
+}
+
+/// Rounds `value * 2^binary_exponent * 10^decimal_exponent` to the nearest
+/// integer, half away from zero.
+pub fn round_scaled(value: &BigInt, binary_exponent: i64, decimal_exponent: i64) -> BigInt {
+    let mut numerator = value.abs();
+    let mut denominator = BigInt::from(1);
+
+    if binary_exponent >= 0 {
+        numerator <<= binary_exponent as usize;
+    } else {
+        denominator <<= (-binary_exponent) as usize;
+    }
+
+    let ten = BigInt::from(10);
+    if decimal_exponent >= 0 {
+        numerator *= ten.pow(decimal_exponent as u32);
+    } else {
+        denominator *= ten.pow((-decimal_exponent) as u32);
+    }
+
+    let rounded: BigInt = (numerator * 2 + &denominator) / (denominator * 2);
+
+    if value.is_negative() {
+        -rounded
+    } else {
+        rounded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_decimals() {
+        assert_eq!(parse("-12.5e-3"), Ok((BigInt::from(-125), -4)));
+        assert_eq!(parse(" +.5E2 "), Ok((BigInt::from(5), 1)));
+        assert_eq!(parse("7."), Ok((BigInt::from(7), 0)));
+
+        for s in ["", ".", "-", "1e", "1.2.3", "0x10", "1e2.5"] {
+            assert_eq!(parse(s), Err(ParseDecimalError), "{s:?}");
+        }
+    }
+
+    #[test]
+    fn bounds_the_exponent_and_digits() {
+        assert!(parse(&format!("1e{MAX_DECIMAL_EXPONENT}")).is_ok());
+        assert!(parse(&format!("1e-{MAX_DECIMAL_EXPONENT}")).is_ok());
+        assert!(parse("1e4001").is_err());
+        assert!(parse("1e-1000000000").is_err());
+        assert!(parse("1e99999999999999999999").is_err());
+
+        let digits = "1".repeat(MAX_DECIMAL_EXPONENT as usize);
+        assert!(parse(&digits).is_ok());
+        assert!(parse(&format!("{digits}1")).is_err());
+    }
+
+    #[test]
+    fn rounds_half_away_from_zero() {
+        assert_eq!(round_scaled(&BigInt::from(5), -1, 0), BigInt::from(3));
+        assert_eq!(round_scaled(&BigInt::from(-5), -1, 0), BigInt::from(-3));
+        assert_eq!(round_scaled(&BigInt::from(3), 0, -1), BigInt::from(0));
+        assert_eq!(round_scaled(&BigInt::from(1), 2, 1), BigInt::from(40));
+    }
+}
@@ -0,0 +1,552 @@
+use std::{error::Error, f64::consts, fmt, str::FromStr};
+
+// Larger powers go through the logarithm rather than a long loop
+const MAX_INTEGER_POWER: f64 = 16.;
+// Bounds on the formulas, whose parsing and compiling recurse as deep as
+// they nest, and as long as the chains of operators for the compiling
+const MAX_NESTING: usize = 64;
+const MAX_TOKENS: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseExpressionError {
+    UnexpectedCharacter(char, usize),
+    InvalidNumber(String, usize),
+    UnknownName(String, usize),
+    UnexpectedToken(String, usize),
+    UnexpectedEnd,
+    TooDeep(usize),
+    TooLong,
+}
+
+impl fmt::Display for ParseExpressionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Positions are counted from 1 like text editor columns
+        match self {
+            Self::UnexpectedCharacter(character, position) => {
+                write!(f, "unexpected '{character}' at column {}", position + 1)
+            }
+            Self::InvalidNumber(number, position) => {
+                write!(f, "invalid number '{number}' at column {}", position + 1)
+            }
+            Self::UnknownName(name, position) => {
+                write!(f, "unknown name '{name}' at column {}", position + 1)
+            }
+            Self::UnexpectedToken(token, position) => {
+                write!(f, "unexpected '{token}' at column {}", position + 1)
+            }
+            Self::UnexpectedEnd => write!(f, "unexpected end of formula"),
+            Self::TooDeep(position) => write!(
+                f,
+                "nested more than {MAX_NESTING} deep at column {}",
+                position + 1
+            ),
+            Self::TooLong => write!(f, "formula longer than {MAX_TOKENS} tokens"),
+        }
+    }
+}
+
+impl Error for ParseExpressionError {}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Variable {
+    Z,
+    C,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Operator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+}
+
+/// Functions of one complex argument, each compiled to the shader function
+/// named `complex_<name>`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Function {
+    Sin,
+    Cos,
+    Tan,
+    Sinh,
+    Cosh,
+    Tanh,
+    Exp,
+    Log,
+    Sqrt,
+    Abs,
+    Conj,
+    Re,
+    Im,
+}
+
+impl Function {
+    const ALL: [Function; 13] = [
+        Function::Sin,
+        Function::Cos,
+        Function::Tan,
+        Function::Sinh,
+        Function::Cosh,
+        Function::Tanh,
+        Function::Exp,
+        Function::Log,
+        Function::Sqrt,
+        Function::Abs,
+        Function::Conj,
+        Function::Re,
+        Function::Im,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Function::Sin => "sin",
+            Function::Cos => "cos",
+            Function::Tan => "tan",
+            Function::Sinh => "sinh",
+            Function::Cosh => "cosh",
+            Function::Tanh => "tanh",
+            Function::Exp => "exp",
+            Function::Log => "log",
+            Function::Sqrt => "sqrt",
+            Function::Abs => "abs",
+            Function::Conj => "conj",
+            Function::Re => "re",
+            Function::Im => "im",
+        }
+    }
+}
+
+/// Complex expression of `z` and `c`, the step iterated by the custom
+/// formula. Its text form is `z = <expression>`, the `z =` being optional,
+/// with `+ - * / ^`, parentheses, the constants `i`, `pi` and `e` and the
+/// functions of [`Function`], like `z = z*z*z + c*sin(z)`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Constant(f64, f64),
+    Variable(Variable),
+    Neg(Box<Expression>),
+    Binary(Operator, Box<Expression>, Box<Expression>),
+    Call(Function, Box<Expression>),
+}
+
+impl Expression {
+    /// WGSL expression of `z` and `c`, both `vec2<f32>`, using the complex
+    /// functions of the shader.
+    pub fn to_wgsl(&self) -> String {
+        match self {
+            Expression::Constant(real, imag) => format!("vec2<f32>({real:?}, {imag:?})"),
+            Expression::Variable(Variable::Z) => "z".to_string(),
+            Expression::Variable(Variable::C) => "c".to_string(),
+            Expression::Neg(value) => format!("(-{})", value.to_wgsl()),
+            Expression::Binary(operator, left, right) => {
+                let (left, right) = (left.to_wgsl(), right.to_wgsl());
+                match operator {
+                    Operator::Add => format!("({left} + {right})"),
+                    Operator::Sub => format!("({left} - {right})"),
+                    Operator::Mul => format!("complex_mul({left}, {right})"),
+                    Operator::Div => format!("complex_div({left}, {right})"),
+                    Operator::Pow => match self.integer_power() {
+                        Some(power) => format!("complex_pow({left}, {power:?})"),
+                        None => format!("complex_cpow({left}, {right})"),
+                    },
+                }
+            }
+            Expression::Call(function, argument) => {
+                format!("complex_{}({})", function.name(), argument.to_wgsl())
+            }
+        }
+    }
+
+    // Small positive integer powers, which complex_pow computes by repeated
+    // multiplication
+    fn integer_power(&self) -> Option<f64> {
+        match self {
+            Expression::Binary(Operator::Pow, _, right) => match **right {
+                Expression::Constant(power, 0.)
+                    if (1. ..=MAX_INTEGER_POWER).contains(&power) && power.fract() == 0. =>
+                {
+                    Some(power)
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Expression {
+    type Err = ParseExpressionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = tokenize(s)?;
+
+        // Drop the optional `z =`
+        if let [(Token::Name(name), _), (Token::Symbol('='), _), ..] = &tokens[..]
+            && name == "z"
+        {
+            tokens.drain(..2);
+        }
+
+        let mut parser = Parser {
+            tokens,
+            index: 0,
+            depth: 0,
+        };
+        let expression = parser.sum()?;
+
+        match parser.next() {
+            Some((token, position)) => Err(ParseExpressionError::UnexpectedToken(
+                token.to_string(),
+                position,
+            )),
+            None => Ok(expression),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Symbol(char),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(value) => write!(f, "{value}"),
+            Token::Name(name) => write!(f, "{name}"),
+            Token::Symbol(symbol) => write!(f, "{symbol}"),
+        }
+    }
+}
+
+/// Splits the text into tokens, each with the index of the character it
+/// starts at.
+fn tokenize(s: &str) -> Result<Vec<(Token, usize)>, ParseExpressionError> {
+    let chars = s.chars().collect::<Vec<_>>();
+    let is_digit = |index: usize| chars.get(index).is_some_and(char::is_ascii_digit);
+
+    let mut tokens = Vec::new();
+    let mut index = 0;
+
+    while let Some(&character) = chars.get(index) {
+        let start = index;
+
+        if character.is_whitespace() {
+            index += 1;
+            continue;
+        }
+
+        let token = if character.is_ascii_digit() || character == '.' {
+            while is_digit(index) || chars.get(index) == Some(&'.') {
+                index += 1;
+            }
+
+            // An exponent, unless the `e` is the constant
+            if matches!(chars.get(index), Some('e' | 'E')) {
+                let sign = matches!(chars.get(index + 1), Some('+' | '-')) as usize;
+                if is_digit(index + 1 + sign) {
+                    index += 1 + sign;
+                    while is_digit(index) {
+                        index += 1;
+                    }
+                }
+            }
+
+            let number = chars[start..index].iter().collect::<String>();
+            // The shader's numbers are f32, past its range they have no
+            // WGSL literal
+            match number.parse::<f64>() {
+                Ok(value) if value.abs() <= f32::MAX as f64 => Token::Number(value),
+                _ => return Err(ParseExpressionError::InvalidNumber(number, start)),
+            }
+        } else if character.is_alphabetic() {
+            while chars
+                .get(index)
+                .is_some_and(|&c| c.is_alphanumeric() || c == '_')
+            {
+                index += 1;
+            }
+
+            Token::Name(chars[start..index].iter().collect())
+        } else if "+-*/^()=".contains(character) {
+            index += 1;
+
+            Token::Symbol(character)
+        } else {
+            return Err(ParseExpressionError::UnexpectedCharacter(character, start));
+        };
+
+        tokens.push((token, start));
+        if tokens.len() > MAX_TOKENS {
+            return Err(ParseExpressionError::TooLong);
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent over the tokens, one method per precedence level.
+/// `depth` counts the unary levels entered, which every nesting goes
+/// through.
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    index: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<(Token, usize)> {
+        let token = self.tokens.get(self.index).cloned();
+        self.index += 1;
+
+        token
+    }
+
+    fn next_symbol_in(&mut self, symbols: &str) -> Option<char> {
+        match self.tokens.get(self.index) {
+            Some((Token::Symbol(symbol), _)) if symbols.contains(*symbol) => {
+                self.index += 1;
+                Some(*symbol)
+            }
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ParseExpressionError> {
+        match self.next() {
+            Some((Token::Symbol(symbol), _)) if symbol == expected => Ok(()),
+            Some((token, position)) => Err(ParseExpressionError::UnexpectedToken(
+                token.to_string(),
+                position,
+            )),
+            None => Err(ParseExpressionError::UnexpectedEnd),
+        }
+    }
+
+    // sum = product (("+" | "-") product)*
+    fn sum(&mut self) -> Result<Expression, ParseExpressionError> {
+        let mut expression = self.product()?;
+        while let Some(symbol) = self.next_symbol_in("+-") {
+            let operator = if symbol == '+' {
+                Operator::Add
+            } else {
+                Operator::Sub
+            };
+            expression = Expression::Binary(operator, expression.into(), self.product()?.into());
+        }
+
+        Ok(expression)
+    }
+
+    // product = unary (("*" | "/") unary)*
+    fn product(&mut self) -> Result<Expression, ParseExpressionError> {
+        let mut expression = self.unary()?;
+        while let Some(symbol) = self.next_symbol_in("*/") {
+            let operator = if symbol == '*' {
+                Operator::Mul
+            } else {
+                Operator::Div
+            };
+            expression = Expression::Binary(operator, expression.into(), self.unary()?.into());
+        }
+
+        Ok(expression)
+    }
+
+    // unary = "-" unary | power
+    fn unary(&mut self) -> Result<Expression, ParseExpressionError> {
+        if self.depth == MAX_NESTING {
+            let position = self
+                .tokens
+                .get(self.index)
+                .map_or(0, |&(_, position)| position);
+            return Err(ParseExpressionError::TooDeep(position));
+        }
+
+        self.depth += 1;
+        let expression = if self.next_symbol_in("-").is_some() {
+            self.unary().map(|value| Expression::Neg(value.into()))
+        } else {
+            self.power()
+        };
+        self.depth -= 1;
+
+        expression
+    }
+
+    // power = atom ("^" unary)?, so powers group to the right and -z^2 is
+    // -(z^2)
+    fn power(&mut self) -> Result<Expression, ParseExpressionError> {
+        let base = self.atom()?;
+        if self.next_symbol_in("^").is_some() {
+            return Ok(Expression::Binary(
+                Operator::Pow,
+                base.into(),
+                self.unary()?.into(),
+            ));
+        }
+
+        Ok(base)
+    }
+
+    // atom = number | constant | variable | function "(" sum ")" | "(" sum ")"
+    fn atom(&mut self) -> Result<Expression, ParseExpressionError> {
+        let (token, position) = self.next().ok_or(ParseExpressionError::UnexpectedEnd)?;
+
+        match token {
+            Token::Number(value) => Ok(Expression::Constant(value, 0.)),
+            Token::Symbol('(') => {
+                let expression = self.sum()?;
+                self.expect(')')?;
+
+                Ok(expression)
+            }
+            Token::Name(name) => match name.as_str() {
+                "z" => Ok(Expression::Variable(Variable::Z)),
+                "c" => Ok(Expression::Variable(Variable::C)),
+                "i" => Ok(Expression::Constant(0., 1.)),
+                "pi" => Ok(Expression::Constant(consts::PI, 0.)),
+                "e" => Ok(Expression::Constant(consts::E, 0.)),
+                _ => {
+                    let function = Function::ALL
+                        .into_iter()
+                        .find(|function| function.name() == name)
+                        .ok_or(ParseExpressionError::UnknownName(name, position))?;
+
+                    self.expect('(')?;
+                    let argument = self.sum()?;
+                    self.expect(')')?;
+
+                    Ok(Expression::Call(function, argument.into()))
+                }
+            },
+            token => Err(ParseExpressionError::UnexpectedToken(
+                token.to_string(),
+                position,
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wgsl(s: &str) -> String {
+        s.parse::<Expression>().unwrap().to_wgsl()
+    }
+
+    #[test]
+    fn parses_with_precedence() {
+        assert_eq!(wgsl("z = z*z + c"), "(complex_mul(z, z) + c)");
+        assert_eq!(wgsl("-z^2"), "(-complex_pow(z, 2.0))");
+        assert_eq!(
+            wgsl("z^2^3"),
+            "complex_cpow(z, complex_pow(vec2<f32>(2.0, 0.0), 3.0))"
+        );
+        assert_eq!(
+            wgsl("(z - c) / 2"),
+            "complex_div((z - c), vec2<f32>(2.0, 0.0))"
+        );
+        assert_eq!(wgsl("c*sin(z)"), "complex_mul(c, complex_sin(z))");
+    }
+
+    #[test]
+    fn compiles_only_small_integer_powers_by_multiplication() {
+        assert_eq!(wgsl("z^16"), "complex_pow(z, 16.0)");
+        assert_eq!(wgsl("z^17"), "complex_cpow(z, vec2<f32>(17.0, 0.0))");
+        assert_eq!(wgsl("z^2.5"), "complex_cpow(z, vec2<f32>(2.5, 0.0))");
+        assert_eq!(wgsl("z^i"), "complex_cpow(z, vec2<f32>(0.0, 1.0))");
+    }
+
+    #[test]
+    fn tells_exponents_from_the_constant_e() {
+        assert_eq!(wgsl("1.5e-3"), "vec2<f32>(0.0015, 0.0)");
+        assert_eq!(
+            wgsl("2*e"),
+            format!(
+                "complex_mul(vec2<f32>(2.0, 0.0), vec2<f32>({:?}, 0.0))",
+                consts::E
+            )
+        );
+        assert_eq!(
+            "2e".parse::<Expression>(),
+            Err(ParseExpressionError::UnexpectedToken("e".to_string(), 1))
+        );
+    }
+
+    #[test]
+    fn reports_errors_with_their_position() {
+        assert_eq!(
+            "z + $".parse::<Expression>(),
+            Err(ParseExpressionError::UnexpectedCharacter('$', 4))
+        );
+        assert_eq!(
+            "1.2.3".parse::<Expression>(),
+            Err(ParseExpressionError::InvalidNumber("1.2.3".to_string(), 0))
+        );
+        assert_eq!(
+            "z + w".parse::<Expression>(),
+            Err(ParseExpressionError::UnknownName("w".to_string(), 4))
+        );
+        assert_eq!(
+            "sin z".parse::<Expression>(),
+            Err(ParseExpressionError::UnexpectedToken("z".to_string(), 4))
+        );
+        assert_eq!(
+            "(z + c".parse::<Expression>(),
+            Err(ParseExpressionError::UnexpectedEnd)
+        );
+        assert_eq!(
+            "".parse::<Expression>(),
+            Err(ParseExpressionError::UnexpectedEnd)
+        );
+        assert_eq!(
+            "z = ".parse::<Expression>(),
+            Err(ParseExpressionError::UnexpectedEnd)
+        );
+    }
+
+    #[test]
+    fn rejects_numbers_past_the_f32_range() {
+        assert_eq!(
+            "z + 1e400".parse::<Expression>(),
+            Err(ParseExpressionError::InvalidNumber("1e400".to_string(), 4))
+        );
+        assert_eq!(
+            "z + 1e100".parse::<Expression>(),
+            Err(ParseExpressionError::InvalidNumber("1e100".to_string(), 4))
+        );
+        assert!("z + 1e38".parse::<Expression>().is_ok());
+        assert!("z + 1e-400".parse::<Expression>().is_ok());
+    }
+
+    #[test]
+    fn bounds_the_nesting_and_length() {
+        let nested = |depth: usize| format!("{}z{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(nested(MAX_NESTING - 1).parse::<Expression>().is_ok());
+        assert_eq!(
+            nested(MAX_NESTING).parse::<Expression>(),
+            Err(ParseExpressionError::TooDeep(MAX_NESTING))
+        );
+        assert_eq!(
+            nested(100_000).parse::<Expression>(),
+            Err(ParseExpressionError::TooLong)
+        );
+        assert!(matches!(
+            format!("{}z", "-".repeat(1000)).parse::<Expression>(),
+            Err(ParseExpressionError::TooDeep(_))
+        ));
+        assert!(matches!(
+            format!("z{}", "^z".repeat(100)).parse::<Expression>(),
+            Err(ParseExpressionError::TooDeep(_))
+        ));
+        assert_eq!(
+            format!("z{}", " + z".repeat(MAX_TOKENS)).parse::<Expression>(),
+            Err(ParseExpressionError::TooLong)
+        );
+    }
+}
@@ -0,0 +1,247 @@
+use std::{
+    fmt,
+    ops::{Add, Mul, Neg, Sub},
+    str::FromStr,
+};
+
+use num_bigint::BigInt;
+use num_traits::{Signed, ToPrimitive, Zero};
+
+use crate::{
+    decimal::{self, ParseDecimalError},
+    floatexp::{self, FloatExp},
+};
+
+/// Arbitrary-precision binary fixed-point number, `mantissa / 2^bits`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fixed {
+    mantissa: BigInt,
+    bits: u32,
+}
+
+impl Fixed {
+    pub fn zero(bits: u32) -> Self {
+        Self {
+            mantissa: BigInt::zero(),
+            bits,
+        }
+    }
+
+    pub fn from_f64(value: f64, bits: u32) -> Self {
+        Self::from_float_exp(FloatExp::from_f64(value), bits)
+    }
+
+    pub fn from_float_exp(value: FloatExp, bits: u32) -> Self {
+        let (mantissa, exponent) = value.to_parts();
+
+        // The mantissa is exactly a 53 bit integer scaled by 2^-53
+        let mut significand = BigInt::from((mantissa.abs() * 2f64.powi(53)) as u64);
+        if mantissa < 0. {
+            significand = -significand;
+        }
+
+        let shift = exponent as i64 - 53 + bits as i64;
+        let mantissa = if shift >= 0 {
+            significand << shift as usize
+        } else if shift >= -54 {
+            round_shift_right(significand, (-shift) as u32)
+        } else {
+            // Rounds to zero whatever the significand
+            BigInt::zero()
+        };
+
+        Self { mantissa, bits }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        // Keep only the top 64 bits so the conversion can't overflow
+        let excess = self.mantissa.bits().saturating_sub(64);
+        let truncated = (&self.mantissa >> excess).to_f64().unwrap_or(0.);
+
+        floatexp::ldexp(truncated, (excess as i64 - self.bits as i64) as i32)
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    pub fn with_bits(&self, bits: u32) -> Self {
+        let mantissa = if bits >= self.bits {
+            &self.mantissa << (bits - self.bits) as usize
+        } else {
+            round_shift_right(self.mantissa.clone(), self.bits - bits)
+        };
+
+        Self { mantissa, bits }
+    }
+
+    pub fn add_float_exp(&self, value: FloatExp) -> Self {
+        Self {
+            mantissa: &self.mantissa + Self::from_float_exp(value, self.bits).mantissa,
+            bits: self.bits,
+        }
+    }
+
+    pub fn abs(&self) -> Self {
+        Self {
+            mantissa: self.mantissa.abs(),
+            bits: self.bits,
+        }
+    }
+
+    pub fn clamp(self, min: f64, max: f64) -> Self {
+        let value = self.to_f64();
+        if value < min {
+            Self::from_f64(min, self.bits)
+        } else if value > max {
+            Self::from_f64(max, self.bits)
+        } else {
+            self
+        }
+    }
+}
+
+/// Printed with just enough decimals to tell the value apart from its
+/// neighbours `2^-bits` away, so parsing it back and restoring the precision
+/// gives the same number.
+impl fmt::Display for Fixed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let decimals = (self.bits as f64 * std::f64::consts::LOG10_2).ceil() as usize + 1;
+        let scaled = decimal::round_scaled(&self.mantissa, -(self.bits as i64), decimals as i64);
+
+        let digits = format!("{:0>width$}", scaled.abs(), width = decimals + 1);
+        let (integer, fraction) = digits.split_at(digits.len() - decimals);
+        let fraction = fraction.trim_end_matches('0');
+
+        if scaled.is_negative() {
+            write!(f, "-")?;
+        }
+        write!(f, "{integer}")?;
+        if !fraction.is_empty() {
+            write!(f, ".{fraction}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parsed with enough bits to hold every decimal given.
+impl FromStr for Fixed {
+    type Err = ParseDecimalError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (digits, exponent) = decimal::parse(s)?;
+        let bits = ((-exponent).max(0) as f64 * std::f64::consts::LOG2_10).ceil() as u32 + 1;
+
+        Ok(Self {
+            mantissa: decimal::round_scaled(&digits, bits as i64, exponent),
+            bits,
+        })
+    }
+}
+
+// Arithmetic is done at the precision of the left operand; the right one is
+// expected to already share it.
+impl Add<&Fixed> for Fixed {
+    type Output = Fixed;
+
+    fn add(self, other: &Fixed) -> Fixed {
+        debug_assert_eq!(self.bits, other.bits);
+        Fixed {
+            mantissa: self.mantissa + &other.mantissa,
+            bits: self.bits,
+        }
+    }
+}
+
+impl Add<Fixed> for Fixed {
+    type Output = Fixed;
+
+    fn add(self, other: Fixed) -> Fixed {
+        self + &other
+    }
+}
+
+impl Sub<Fixed> for Fixed {
+    type Output = Fixed;
+
+    fn sub(self, other: Fixed) -> Fixed {
+        debug_assert_eq!(self.bits, other.bits);
+        Fixed {
+            mantissa: self.mantissa - other.mantissa,
+            bits: self.bits,
+        }
+    }
+}
+
+impl Mul<&Fixed> for &Fixed {
+    type Output = Fixed;
+
+    fn mul(self, other: &Fixed) -> Fixed {
+        debug_assert_eq!(self.bits, other.bits);
+        Fixed {
+            mantissa: (&self.mantissa * &other.mantissa) >> self.bits as usize,
+            bits: self.bits,
+        }
+    }
+}
+
+impl Neg for &Fixed {
+    type Output = Fixed;
+
+    fn neg(self) -> Fixed {
+        Fixed {
+            mantissa: -&self.mantissa,
+            bits: self.bits,
+        }
+    }
+}
+
+/// Shifts right by `shift` bits, rounding half away from zero.
+fn round_shift_right(value: BigInt, shift: u32) -> BigInt {
+    if shift == 0 {
+        return value;
+    }
+
+    let half = BigInt::from(1) << (shift - 1) as usize;
+    if value.is_negative() {
+        -((-value + half) >> shift as usize)
+    } else {
+        (value + half) >> shift as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_from_and_to_f64() {
+        for value in [0., 1.5, -0.75, 0.1, -3.999] {
+            assert_eq!(Fixed::from_f64(value, 64).to_f64(), value);
+        }
+        assert_eq!(Fixed::from_f64(0.1, 4).to_f64(), 0.125);
+        assert_eq!(Fixed::from_f64(1e-30, 64), Fixed::zero(64));
+    }
+
+    #[test]
+    fn round_trips_through_text() {
+        assert_eq!(Fixed::from_f64(-0.75, 8).to_string(), "-0.75");
+        assert_eq!("-0.75".parse::<Fixed>().unwrap().to_f64(), -0.75);
+
+        for bits in [4, 64, 200] {
+            for value in [0.1, -1.3, 2. / 3.] {
+                let fixed = Fixed::from_f64(value, bits);
+                let parsed = fixed.to_string().parse::<Fixed>().unwrap();
+                assert_eq!(parsed.with_bits(bits), fixed);
+            }
+        }
+    }
+
+    #[test]
+    fn rescales_the_precision() {
+        let fixed = Fixed::from_f64(0.3, 60);
+        assert_eq!(fixed.with_bits(100).with_bits(60), fixed);
+        assert_eq!(fixed.with_bits(2).to_f64(), 0.25);
+    }
+}
@@ -0,0 +1,297 @@
+use std::{
+    cmp::Ordering,
+    fmt,
+    ops::{Add, Mul, Neg, Sub},
+    str::FromStr,
+};
+
+use num_bigint::BigInt;
+use num_traits::{Signed, ToPrimitive, Zero};
+
+use crate::decimal::{self, ParseDecimalError};
+
+/// Extended-range float, `mantissa * 2^exponent`, for values past the f64
+/// exponent range. The mantissa is kept in [0.5, 1) unless the value is zero.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FloatExp {
+    mantissa: f64,
+    exponent: i32,
+}
+
+impl FloatExp {
+    pub const ZERO: FloatExp = FloatExp {
+        mantissa: 0.,
+        exponent: 0,
+    };
+
+    pub fn new(mantissa: f64, exponent: i32) -> Self {
+        if mantissa == 0. || !mantissa.is_finite() {
+            return Self::ZERO;
+        }
+
+        let (mantissa, shift) = frexp(mantissa);
+
+        Self {
+            mantissa,
+            exponent: exponent + shift,
+        }
+    }
+
+    pub fn from_f64(value: f64) -> Self {
+        Self::new(value, 0)
+    }
+
+    pub fn to_f64(self) -> f64 {
+        ldexp(self.mantissa, self.exponent)
+    }
+
+    /// The mantissa, in [0.5, 1) or zero, and the power of two it is scaled
+    /// by.
+    pub fn to_parts(self) -> (f64, i32) {
+        (self.mantissa, self.exponent)
+    }
+
+    pub fn mul_f64(self, value: f64) -> Self {
+        Self::new(self.mantissa * value, self.exponent)
+    }
+
+    pub fn log2(self) -> f64 {
+        self.mantissa.abs().log2() + self.exponent as f64
+    }
+}
+
+impl PartialOrd for FloatExp {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        // Not `signum`, which is 1 for zero
+        let sign = self
+            .mantissa
+            .partial_cmp(&0.)?
+            .cmp(&other.mantissa.partial_cmp(&0.)?);
+        if sign != Ordering::Equal || self.mantissa == 0. {
+            return Some(sign);
+        }
+
+        let magnitude = self
+            .exponent
+            .cmp(&other.exponent)
+            .then(self.mantissa.abs().partial_cmp(&other.mantissa.abs())?);
+
+        Some(if self.mantissa > 0. {
+            magnitude
+        } else {
+            magnitude.reverse()
+        })
+    }
+}
+
+impl Add for FloatExp {
+    type Output = FloatExp;
+
+    fn add(self, other: FloatExp) -> FloatExp {
+        if self.mantissa == 0. {
+            return other;
+        }
+        if other.mantissa == 0. {
+            return self;
+        }
+
+        // Align on the larger exponent, the smaller operand may vanish
+        let (larger, smaller) = if self.exponent >= other.exponent {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        let shift = (smaller.exponent - larger.exponent).max(-1100);
+
+        FloatExp::new(
+            larger.mantissa + ldexp(smaller.mantissa, shift),
+            larger.exponent,
+        )
+    }
+}
+
+impl Sub for FloatExp {
+    type Output = FloatExp;
+
+    fn sub(self, other: FloatExp) -> FloatExp {
+        self + -other
+    }
+}
+
+impl Mul for FloatExp {
+    type Output = FloatExp;
+
+    fn mul(self, other: FloatExp) -> FloatExp {
+        FloatExp::new(
+            self.mantissa * other.mantissa,
+            self.exponent + other.exponent,
+        )
+    }
+}
+
+impl Neg for FloatExp {
+    type Output = FloatExp;
+
+    fn neg(self) -> FloatExp {
+        FloatExp::new(-self.mantissa, self.exponent)
+    }
+}
+
+/// Printed with 17 significant digits, enough for the f64 mantissa to parse
+/// back to the same value.
+impl fmt::Display for FloatExp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.mantissa == 0. {
+            return write!(f, "0e0");
+        }
+
+        // The mantissa is a 53 bit integer scaled by 2^-53
+        let significand = BigInt::from((self.mantissa.abs() * 2f64.powi(53)) as u64);
+        let binary_exponent = self.exponent as i64 - 53;
+
+        let lowest = BigInt::from(10u64.pow(16));
+        let highest = BigInt::from(10u64.pow(17));
+        let mut decimal_exponent = (self.log2() * std::f64::consts::LOG10_2).floor() as i64 - 16;
+        let digits = loop {
+            let digits = decimal::round_scaled(&significand, binary_exponent, -decimal_exponent);
+            if digits >= highest {
+                decimal_exponent += 1;
+            } else if digits < lowest {
+                decimal_exponent -= 1;
+            } else {
+                break digits.to_string();
+            }
+        };
+
+        let (integer, fraction) = digits.split_at(1);
+        let fraction = fraction.trim_end_matches('0');
+
+        if self.mantissa < 0. {
+            write!(f, "-")?;
+        }
+        write!(f, "{integer}")?;
+        if !fraction.is_empty() {
+            write!(f, ".{fraction}")?;
+        }
+        write!(f, "e{}", decimal_exponent + 16)
+    }
+}
+
+impl FromStr for FloatExp {
+    type Err = ParseDecimalError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (digits, decimal_exponent) = decimal::parse(s)?;
+        if digits.is_zero() {
+            return Ok(Self::ZERO);
+        }
+
+        // Look for the 53 bit integer closest to the decimal once scaled
+        let lowest = BigInt::from(1u64 << 52);
+        let highest = BigInt::from(1u64 << 53);
+        let mut binary_exponent = (digits.bits() as f64
+            + decimal_exponent as f64 * std::f64::consts::LOG2_10)
+            .floor() as i64
+            - 53;
+        let significand = loop {
+            let significand = decimal::round_scaled(&digits, -binary_exponent, decimal_exponent);
+            if significand.abs() >= highest {
+                binary_exponent += 1;
+            } else if significand.abs() < lowest {
+                binary_exponent -= 1;
+            } else {
+                break significand;
+            }
+        };
+
+        let exponent = i32::try_from(binary_exponent + 53).map_err(|_| ParseDecimalError)?;
+
+        Ok(Self::new(
+            significand.to_f64().ok_or(ParseDecimalError)? * 2f64.powi(-53),
+            exponent,
+        ))
+    }
+}
+
+/// Splits a finite, non zero value into a mantissa in [0.5, 1) and a power
+/// of two.
+fn frexp(value: f64) -> (f64, i32) {
+    let biased_exponent = ((value.to_bits() >> 52) & 0x7ff) as i32;
+    if biased_exponent == 0 {
+        // Subnormal, scale it into the normal range first
+        let (mantissa, exponent) = frexp(value * 2f64.powi(64));
+        return (mantissa, exponent - 64);
+    }
+
+    let exponent = biased_exponent - 1022;
+    let mantissa = f64::from_bits((value.to_bits() & !(0x7ff << 52)) | (1022 << 52));
+
+    (mantissa, exponent)
+}
+
+/// `value * 2^exponent`, split in steps so intermediate powers don't
+/// overflow or underflow before the product does.
+pub fn ldexp(mut value: f64, mut exponent: i32) -> f64 {
+    while exponent > 1000 {
+        value *= 2f64.powi(1000);
+        exponent -= 1000;
+    }
+    while exponent < -1000 {
+        value *= 2f64.powi(-1000);
+        exponent += 1000;
+    }
+
+    value * 2f64.powi(exponent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orders_around_zero() {
+        let tiny = FloatExp::new(1., -5000);
+        let huge = FloatExp::new(1., 5000);
+
+        assert!(FloatExp::ZERO < tiny);
+        assert!(-tiny < FloatExp::ZERO);
+        assert!(tiny < huge);
+        assert!(-huge < -tiny);
+        assert!(FloatExp::from_f64(0.75) < FloatExp::from_f64(1.));
+        assert!(FloatExp::from_f64(-1.) < FloatExp::from_f64(-0.75));
+        assert_eq!(
+            FloatExp::ZERO.partial_cmp(&FloatExp::ZERO),
+            Some(Ordering::Equal)
+        );
+    }
+
+    #[test]
+    fn converts_from_and_to_f64() {
+        for value in [0., 1., -0.1, 3e300, 5e-324] {
+            assert_eq!(FloatExp::from_f64(value).to_f64(), value);
+        }
+        assert_eq!(FloatExp::from_f64(f64::NAN), FloatExp::ZERO);
+        assert_eq!(FloatExp::new(1., -5000).to_f64(), 0.);
+    }
+
+    #[test]
+    fn round_trips_through_text() {
+        assert_eq!("0".parse::<FloatExp>(), Ok(FloatExp::ZERO));
+        assert_eq!("0.1".parse::<FloatExp>().unwrap().to_f64(), 0.1);
+        assert_eq!(FloatExp::from_f64(-2.5).to_string(), "-2.5e0");
+
+        for value in [
+            FloatExp::from_f64(0.1),
+            FloatExp::new(-0.6, -3000),
+            FloatExp::new(0.9, 3000),
+        ] {
+            assert_eq!(value.to_string().parse::<FloatExp>(), Ok(value));
+        }
+    }
+
+    #[test]
+    fn rejects_exponents_past_the_decimal_limit() {
+        assert!("1e-1000000000".parse::<FloatExp>().is_err());
+        assert!("1e1000000000".parse::<FloatExp>().is_err());
+    }
+}
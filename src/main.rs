@@ -377,16 +377,27 @@
 //     })
 // }
 
+mod buddhabrot;
+mod camera;
 mod controls;
+mod decimal;
+mod expression;
+mod fixed;
+mod floatexp;
+mod mandelbulb;
+mod newton;
 mod params;
+mod perturbation;
+mod pipeline;
 mod scene;
+mod terrain;
 mod uniform;
 
-use controls::Controls;
-use iced_winit::winit::dpi::{PhysicalPosition, PhysicalSize};
+use controls::{Controls, Panel};
+use iced_winit::winit::dpi::PhysicalSize;
 use iced_winit::winit::event::{ElementState, KeyEvent, MouseScrollDelta};
-use iced_winit::winit::keyboard::{Key, KeyCode, NamedKey, PhysicalKey};
-use scene::Scene;
+use iced_winit::winit::keyboard::Key;
+use scene::{Capabilities, Scene};
 
 use iced_wgpu::graphics::{Shell, Viewport};
 use iced_wgpu::{Engine, Renderer, wgpu};
@@ -410,7 +421,9 @@ use winit::{
 use std::sync::Arc;
 
 use crate::controls::Message;
-use crate::params::Zoom;
+use crate::params::{
+    BuddhabrotParams, LightParams, MandelbulbParams, TerrainParams, TrapParams, Zoom,
+};
 
 pub fn main() -> Result<(), winit::error::EventLoopError> {
     // tracing_subscriber::fmt::init();
@@ -438,6 +451,8 @@ pub fn main() -> Result<(), winit::error::EventLoopError> {
             modifiers: ModifiersState,
             resized: bool,
             drag: bool,
+            click: bool,
+            panel: Panel,
             physical_size: PhysicalSize<u32>,
         },
     }
@@ -485,7 +500,9 @@ pub fn main() -> Result<(), winit::error::EventLoopError> {
                             .request_device(&wgpu::DeviceDescriptor {
                                 label: None,
                                 required_features: adapter_features & wgpu::Features::default(),
-                                required_limits: wgpu::Limits::default(),
+                                // Whatever the adapter allows, as WebGL2 can't meet the
+                                // defaults. The scene checks what it got.
+                                required_limits: adapter.limits(),
                                 memory_hints: wgpu::MemoryHints::MemoryUsage,
                                 trace: wgpu::Trace::Off,
                                 experimental_features: wgpu::ExperimentalFeatures::disabled(),
@@ -522,13 +539,16 @@ pub fn main() -> Result<(), winit::error::EventLoopError> {
                 );
 
                 // Initialize scene and GUI controls
+                let capabilities = Capabilities::new(&adapter, &device);
                 let scene = Scene::new(
                     &device,
+                    capabilities,
                     format,
                     [physical_size.width as f32, physical_size.height as f32],
                 );
 
-                let controls = Controls::new();
+                let mut controls = Controls::new();
+                controls.buddhabrot_supported = capabilities.buddhabrot;
 
                 // Initialize iced
 
@@ -565,6 +585,8 @@ pub fn main() -> Result<(), winit::error::EventLoopError> {
                     viewport,
                     resized: false,
                     drag: false,
+                    click: false,
+                    panel: Panel::default(),
                     physical_size,
                 };
             }
@@ -593,13 +615,15 @@ pub fn main() -> Result<(), winit::error::EventLoopError> {
                 cache,
                 resized,
                 drag,
+                click,
+                panel,
                 physical_size,
             } = self
             else {
                 return;
             };
 
-            let mut window_size = physical_size;
+            let window_size = physical_size;
 
             match event {
                 WindowEvent::RedrawRequested => {
@@ -625,7 +649,7 @@ pub fn main() -> Result<(), winit::error::EventLoopError> {
                             },
                         );
 
-                        scene.resize([size.width as f32, size.height as f32]);
+                        scene.resize(device, [size.width as f32, size.height as f32]);
 
                         *resized = false;
                     }
@@ -636,49 +660,195 @@ pub fn main() -> Result<(), winit::error::EventLoopError> {
                                 .texture
                                 .create_view(&wgpu::TextureViewDescriptor::default());
 
-                            let mut encoder =
-                                device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                                    label: None,
-                                });
+                            let color_params = *scene.get_color_params();
+                            let (params_hue, params_saturation, param_lightness) =
+                                color_params.get_hsl();
+                            let (hue, saturation, lightness) = controls.color;
+                            if params_hue != hue
+                                || params_saturation != saturation
+                                || param_lightness != lightness
+                            {
+                                scene.set_hsl((hue, saturation, lightness));
+                            }
 
+                            let (hue_link, saturation_link, lightness_link) =
+                                controls.color_linked;
+                            let (params_hue_link, params_saturation_link, param_lightness_link) =
+                                color_params.get_link();
+                            if hue_link != params_hue_link
+                                || saturation_link != params_saturation_link
+                                || lightness_link != param_lightness_link
                             {
-                                // Clear the frame
-                                let mut render_pass =
-                                    Scene::clear(&view, &mut encoder, controls.background_color());
-
-                                let color_params = *scene.get_color_params();
-                                let (params_hue, params_saturation, param_lightness) =
-                                    color_params.get_hsl();
-                                let (hue, saturation, lightness) = controls.color;
-                                if params_hue != hue
-                                    || params_saturation != saturation
-                                    || param_lightness != lightness
-                                {
-                                    scene.set_hsl((hue, saturation, lightness));
-                                }
-
-                                let (hue_link, saturation_link, lightness_link) =
-                                    controls.color_linked;
-                                let (params_hue_link, params_saturation_link, param_lightness_link) =
-                                    color_params.get_link();
-                                if hue_link != params_hue_link
-                                    || saturation_link != params_saturation_link
-                                    || lightness_link != param_lightness_link
-                                {
-                                    scene.set_hsl_link((hue_link, saturation_link, lightness_link));
-                                }
-
-                                let iterations = controls.iterations;
-                                if **scene.iterations != iterations {
-                                    scene.set_iterations(iterations);
-                                }
-
-                                // Draw the scene
-                                scene.draw(&mut render_pass, queue);
+                                scene.set_hsl_link((hue_link, saturation_link, lightness_link));
+                            }
+
+                            if color_params.is_smooth() != controls.smooth {
+                                scene.set_smooth(controls.smooth);
+                            }
+
+                            if color_params.get_coloring() != controls.coloring {
+                                scene.set_coloring(controls.coloring);
+                            }
+
+                            if color_params.has_interior_overlay() != controls.interior_overlay {
+                                scene.set_interior_overlay(controls.interior_overlay);
+                            }
+
+                            if color_params.get_stripe_density() != controls.stripe_density {
+                                scene.set_stripe_density(controls.stripe_density);
                             }
 
-                            // Submit the scene
-                            queue.submit([encoder.finish()]);
+                            if color_params.get_triangle_density() != controls.triangle_density {
+                                scene.set_triangle_density(controls.triangle_density);
+                            }
+
+                            let trap_params = TrapParams::new(
+                                controls.trap_shape,
+                                controls.trap_center,
+                                controls.trap_size,
+                            );
+                            if *scene.get_trap_params() != trap_params {
+                                scene.set_trap_params(trap_params);
+                            }
+
+                            let light_params = LightParams::new(controls.lighting, controls.light);
+                            if *scene.get_light_params() != light_params {
+                                scene.set_light_params(light_params);
+                            }
+
+                            let iterations = controls.iterations;
+                            if **scene.iterations != iterations {
+                                scene.set_iterations(iterations);
+                            }
+
+                            let buddhabrot_params = controls.buddhabrot.then(|| {
+                                // The Buddhabrot only counts with the red limit
+                                let iterations = if controls.nebulabrot {
+                                    controls.nebulabrot_iterations
+                                } else {
+                                    [controls.iterations; 3]
+                                };
+
+                                BuddhabrotParams::new(
+                                    controls.nebulabrot,
+                                    iterations,
+                                    controls.normalization,
+                                    controls.gamma,
+                                )
+                            });
+                            if scene.get_buddhabrot_params() != buddhabrot_params {
+                                scene.set_buddhabrot_params(device, buddhabrot_params);
+                            }
+
+                            let mandelbulb_params = controls.mandelbulb.then(|| {
+                                MandelbulbParams::new(
+                                    controls.bulb_power,
+                                    controls.soft_shadows,
+                                    controls.ambient_occlusion,
+                                    controls.fog,
+                                )
+                            });
+                            if scene.get_mandelbulb_params() != mandelbulb_params {
+                                scene.set_mandelbulb_params(device, mandelbulb_params);
+                            }
+
+                            let terrain_params = controls.terrain.then(|| {
+                                TerrainParams::new(controls.height_scale, controls.contours)
+                            });
+                            if scene.get_terrain_params() != terrain_params {
+                                scene.set_terrain_params(device, terrain_params);
+                            }
+
+                            if let Some(coordinates) = controls.goto.take() {
+                                scene.set_coordinates(coordinates);
+                                // Zooms past what the formula resolves stop short
+                                let _ = controls.update(Message::LocationChanged(
+                                    scene.get_coordinates().to_string(),
+                                ));
+                            }
+
+                            if let Some(roots) = controls.newton_roots.take() {
+                                scene.set_newton_roots(&roots);
+                            }
+
+                            if let Some(lyapunov_params) = controls.lyapunov_params.take() {
+                                scene.set_lyapunov_params(lyapunov_params);
+                            }
+
+                            if let Some(hybrid_params) = controls.hybrid_params.take() {
+                                scene.set_hybrid_params(hybrid_params);
+                            }
+
+                            if let Some(expression) = controls.custom_expression.take() {
+                                scene.set_custom_formula(device, &expression);
+                            }
+                            if let Some(result) = scene.poll_custom_formula() {
+                                controls.custom_formula_error =
+                                    result.err().map(|error| error.to_string());
+                            }
+
+                            if scene.get_fractal_params().get_formula() != controls.formula {
+                                scene.set_formula(controls.formula);
+                                let _ = controls.update(Message::LocationChanged(
+                                    scene.get_coordinates().to_string(),
+                                ));
+                            }
+
+                            if scene.get_fractal_params().get_exponent() != controls.exponent {
+                                scene.set_exponent(controls.exponent);
+                                // The scene snaps exponents close to integers
+                                let _ = controls.update(Message::ExponentChanged(
+                                    scene.get_fractal_params().get_exponent(),
+                                ));
+                                let _ = controls.update(Message::LocationChanged(
+                                    scene.get_coordinates().to_string(),
+                                ));
+                            }
+                            // Framing the new sets waits for the slider's release
+                            if std::mem::take(&mut controls.exponent_committed) {
+                                scene.reset_views();
+                                let _ = controls.update(Message::LocationChanged(
+                                    scene.get_coordinates().to_string(),
+                                ));
+                            }
+
+                            if scene.get_fractal_params().get_phoenix() != controls.phoenix {
+                                scene.set_phoenix(controls.phoenix);
+                            }
+
+                            if scene.get_fractal_params().get_relaxation() != controls.relaxation {
+                                scene.set_relaxation(controls.relaxation);
+                            }
+
+                            if scene.get_fractal_params().get_bailout_radius()
+                                != controls.bailout_radius
+                            {
+                                scene.set_bailout_radius(controls.bailout_radius);
+                            }
+
+                            if scene.get_fractal_params().get_bailout_norm()
+                                != controls.bailout_norm
+                            {
+                                scene.set_bailout_norm(controls.bailout_norm);
+                            }
+
+                            let julia = (controls.julia || controls.julia_preview)
+                                && controls.formula.has_julia();
+                            if scene.get_fractal_params().is_julia() != julia {
+                                scene.set_julia(julia);
+                                let _ = controls.update(Message::LocationChanged(
+                                    scene.get_coordinates().to_string(),
+                                ));
+                            }
+
+                            // Draw the scene
+                            scene.render(device, queue, &view, controls.background_color());
+                            controls.skipped_iterations = scene.get_skipped_iterations();
+
+                            // The Buddhabrot and the glitch fixes build up over frames
+                            if scene.needs_redraw() {
+                                window.request_redraw();
+                            }
 
                             // Draw iced on top
                             let mut interface = UserInterface::build(
@@ -739,8 +909,26 @@ pub fn main() -> Result<(), winit::error::EventLoopError> {
                         && let Some(position) = cursor.position()
                     {
                         println!("position: {} - window_size: {:?}", position, window_size);
-                        *drag = state == winit::event::ElementState::Pressed
-                            && (position.y as u32) < window_size.height - 180;
+                        let pressed = state == winit::event::ElementState::Pressed;
+
+                        // A click without dragging picks the constant of the Julia set
+                        if !pressed
+                            && *click
+                            && !controls.julia
+                            && !controls.mandelbulb
+                            && !controls.terrain
+                        {
+                            let constant = scene.mandelbrot_point_at((
+                                position.x * viewport.scale_factor(),
+                                position.y * viewport.scale_factor(),
+                            ));
+                            scene.set_julia_constant(constant);
+                            let _ = controls
+                                .update(Message::JuliaConstantChanged(constant.0, constant.1));
+                        }
+
+                        *drag = pressed && !panel.bounds.contains(position);
+                        *click = *drag;
                     }
                 }
                 WindowEvent::MouseWheel { delta, .. } => {
@@ -749,21 +937,54 @@ pub fn main() -> Result<(), winit::error::EventLoopError> {
                             MouseScrollDelta::LineDelta(_, y) => y,
                             MouseScrollDelta::PixelDelta(delta) => delta.y as f32,
                         };
-                        scene.zoom(
-                            if y < 0. { Zoom::Out } else { Zoom::In },
-                            Some((position.x as f32, position.y as f32)),
-                        );
+                        let zoom = if y < 0. { Zoom::Out } else { Zoom::In };
+                        // Shift zooms the view under the terrain, about its
+                        // center as the cursor is over the grid
+                        if controls.mandelbulb || (controls.terrain && !modifiers.shift_key()) {
+                            scene.dolly_camera(zoom);
+                        } else {
+                            let cursor = (!controls.terrain).then_some((position.x, position.y));
+                            scene.zoom(zoom, cursor);
+                            let _ = controls.update(Message::LocationChanged(
+                                scene.get_coordinates().to_string(),
+                            ));
+                        }
+                    }
+                }
+                WindowEvent::KeyboardInput {
+                    event:
+                        KeyEvent {
+                            logical_key: Key::Character(ref character),
+                            state,
+                            repeat: false,
+                            ..
+                        },
+                    ..
+                } if character == "j" && (!panel.typing || state == ElementState::Released) => {
+                    // Preview the Julia set of the point under the cursor
+                    // while the key is held
+                    controls.julia_preview = state == ElementState::Pressed;
+                    if controls.julia_preview {
+                        if let Some(position) = cursor.position() {
+                            scene.set_julia_constant(scene.mandelbrot_point_at((
+                                position.x * viewport.scale_factor(),
+                                position.y * viewport.scale_factor(),
+                            )));
+                        }
+                    } else {
+                        scene.set_julia_constant(controls.julia_constant);
                     }
+                    window.request_redraw();
                 }
                 WindowEvent::KeyboardInput {
                     event:
                         KeyEvent {
-                            logical_key: ref logical_key,
+                            ref logical_key,
                             state: ElementState::Released,
                             ..
                         },
                     ..
-                } => {
+                } if !panel.typing => {
                     let increment = if *logical_key == Key::Character("i".into()) {
                         1000
                     } else if *logical_key == Key::Character("o".into()) {
@@ -774,7 +995,7 @@ pub fn main() -> Result<(), winit::error::EventLoopError> {
 
                     if increment != 0 {
                         scene.set_iterations(scene.iterations.wrapping_add(increment));
-                        controls.update(Message::IterationsChange(Ok(**scene.iterations)));
+                        let _ = controls.update(Message::IterationsChange(Ok(**scene.iterations)));
                     }
                 }
                 WindowEvent::CursorMoved { position, .. } => {
@@ -782,10 +1003,27 @@ pub fn main() -> Result<(), winit::error::EventLoopError> {
                         println!("cursor {:?}", cursor);
                         // println!("viewport {:?}", viewport);
                         println!("position {:?}", position);
-                        scene.move_center((
+                        let motion = (
                             (cur_position.x * viewport.scale_factor() - position.x as f32),
                             (cur_position.y * viewport.scale_factor() - position.y as f32),
-                        ));
+                        );
+                        // Shift drags the view under the terrain
+                        if controls.mandelbulb || (controls.terrain && !modifiers.shift_key()) {
+                            scene.orbit_camera(motion);
+                        } else {
+                            scene.move_center(motion);
+                            let _ = controls.update(Message::LocationChanged(
+                                scene.get_coordinates().to_string(),
+                            ));
+                        }
+                        *click = false;
+                    }
+
+                    if controls.julia_preview {
+                        scene.set_julia_constant(
+                            scene.mandelbrot_point_at((position.x as f32, position.y as f32)),
+                        );
+                        window.request_redraw();
                     }
 
                     *cursor = mouse::Cursor::Available(conversion::cursor_position(
@@ -826,12 +1064,15 @@ pub fn main() -> Result<(), winit::error::EventLoopError> {
 
                 let _ = interface.update(events, *cursor, renderer, clipboard, &mut messages);
 
+                *panel = Panel::default();
+                interface.operate(renderer, panel);
+
                 events.clear();
                 *cache = interface.into_cache();
 
                 // update our UI with any messages
                 for message in messages {
-                    controls.update(message);
+                    let _ = controls.update(message);
                 }
 
                 // and request a redraw
@@ -0,0 +1,115 @@
+use iced_wgpu::{
+    core::Color,
+    wgpu::{self, ShaderStages},
+};
+
+use crate::{
+    camera::OrbitCamera,
+    params::{ColorParams, MandelbulbParams, Viewport, Zoom},
+    pipeline,
+    scene::Scene,
+    uniform::{Bindings, Uniform},
+};
+
+// Sphere the rays are marched in, as in mandelbulb.wgsl. The bulb fits in
+// it for every power, growing from radius 1.2 at power 8 as the power drops.
+const BOUNDING_RADIUS: f32 = 2.;
+// Kept outside the bounding sphere, so never inside the bulb
+const MIN_DISTANCE: f32 = BOUNDING_RADIUS * 1.1;
+const MAX_DISTANCE: f32 = 10.;
+// Starting camera, looking at the bulb from slightly above
+const DEFAULT_CAMERA: (f32, f32, f32) = (0.6, 0.4, 3.);
+
+/// Renderer of the Mandelbulb, raymarched with its distance estimate in a
+/// single fragment pass. The camera orbits the origin, turned by dragging
+/// and moved closer or farther with the wheel.
+pub struct Mandelbulb {
+    pipeline: wgpu::RenderPipeline,
+    params: Uniform<MandelbulbParams>,
+    bindings: Bindings,
+    camera: OrbitCamera,
+}
+
+impl Mandelbulb {
+    pub fn new(
+        device: &wgpu::Device,
+        texture_format: wgpu::TextureFormat,
+        viewport: &Uniform<Viewport>,
+        color_params: &Uniform<ColorParams>,
+        params: MandelbulbParams,
+    ) -> Self {
+        let params = Uniform::new("MandelbulbParams", params, device);
+        let bindings = Bindings::new(
+            "Mandelbulb",
+            &[viewport, color_params, &params],
+            ShaderStages::FRAGMENT,
+            device,
+        );
+
+        let shader =
+            pipeline::shader_module(device, "Mandelbulb Shader", include_str!("mandelbulb.wgsl"));
+        let pipeline_layout = pipeline::pipeline_layout(device, &[&bindings]);
+        let pipeline = pipeline::render_pipeline(
+            device,
+            "Mandelbulb",
+            &pipeline_layout,
+            &shader,
+            ("vs_main", "fs_main"),
+            pipeline::replace_target(texture_format),
+            None,
+        );
+
+        Self {
+            pipeline,
+            params,
+            bindings,
+            camera: OrbitCamera::new(DEFAULT_CAMERA, (MIN_DISTANCE, MAX_DISTANCE)),
+        }
+    }
+
+    pub fn get_params(&self) -> MandelbulbParams {
+        self.params.set_camera((0., 0., 0.))
+    }
+
+    pub fn set_params(&mut self, params: MandelbulbParams) {
+        self.params.set(params);
+    }
+
+    /// Turns the camera around the bulb by the `motion` of the cursor, in
+    /// pixels.
+    pub fn orbit(&mut self, motion: (f32, f32)) {
+        self.camera.orbit(motion);
+    }
+
+    /// Moves the camera toward or away from the bulb.
+    pub fn dolly(&mut self, zoom: Zoom) {
+        self.camera.dolly(zoom);
+    }
+
+    /// Draws the bulb seen from the camera. The viewport and colour
+    /// parameters must already be uploaded.
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        target: &wgpu::TextureView,
+        background_color: Color,
+    ) {
+        self.params.set(self.params.set_camera(self.camera.get()));
+        self.params.upload(queue);
+
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        {
+            let mut render_pass =
+                Scene::begin_render_pass(target, &mut encoder, Some(background_color));
+
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_bind_group(0, self.bindings.get_bind_group(), &[]);
+            render_pass.draw(0..6, 0..1);
+        }
+
+        queue.submit([encoder.finish()]);
+    }
+}
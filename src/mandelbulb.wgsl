@@ -0,0 +1,188 @@
+// Mandelbulb: the set of points whose orbits under z^n + c, with the power
+// taken in spherical coordinates, stay bounded. Raymarched with its distance
+// estimate, lit by a light over the camera with soft shadows and ambient
+// occlusion, and faded into a fog of the HSL colour.
+
+struct MandelbulbParams {
+    power: f32,
+    soft_shadows: i32,
+    ambient_occlusion: i32,
+    fog: f32,
+    yaw: f32,
+    pitch: f32,
+    distance: f32,
+};
+
+@group(0) @binding(0)
+var<uniform> viewport: Viewport;
+
+@group(0) @binding(1)
+var<uniform> color_params: ColorParams;
+
+@group(0) @binding(2)
+var<uniform> params: MandelbulbParams;
+
+// Iterations of the distance estimate, enough for the surface to converge
+const BULB_ITERATIONS: i32 = 12;
+const BULB_BAILOUT: f32 = 2.0;
+// Sphere around the bulb for every power, the rays only march inside it
+const BOUNDING_RADIUS: f32 = 2.0;
+const MAX_STEPS: i32 = 256;
+const SHADOW_STEPS: i32 = 64;
+// Softness of the shadow edges, sharper the higher
+const SHADOW_SHARPNESS: f32 = 16.0;
+const OCCLUSION_SAMPLES: i32 = 5;
+// Distance from the eye to the screen, for a field of view of about 67
+// degrees vertically
+const FOCAL_LENGTH: f32 = 1.5;
+const AMBIENT: f32 = 0.15;
+
+// Distance estimate 0.5 |z| log|z| / |dz| to the bulb in x, and the smallest
+// |z|^2 the orbit came to in y, which colours the surface.
+fn bulb_distance(c: vec3<f32>) -> vec2<f32> {
+    var z = c;
+    var derivative = 1.0;
+    var radius = length(z);
+    var trap = dot(z, z);
+
+    for (var i = 0; i < BULB_ITERATIONS && radius < BULB_BAILOUT; i++) {
+        derivative = params.power * pow(radius, params.power - 1.0) * derivative + 1.0;
+
+        // The polar angle from the vertical axis and the azimuth, both
+        // multiplied by the power
+        let theta = acos(clamp(z.y / max(radius, 1e-20), -1.0, 1.0)) * params.power;
+        let phi = atan2(z.x, z.z) * params.power;
+        z = pow(radius, params.power) * vec3(sin(theta) * sin(phi), cos(theta), sin(theta) * cos(phi)) + c;
+
+        radius = length(z);
+        trap = min(trap, dot(z, z));
+    }
+
+    return vec2(0.5 * log(radius) * radius / derivative, trap);
+}
+
+// Normal from the gradient of the estimate, sampled at the corners of a
+// tetrahedron
+fn bulb_normal(position: vec3<f32>, epsilon: f32) -> vec3<f32> {
+    let offset = vec2(1.0, -1.0) * epsilon;
+
+    return normalize(
+        offset.xyy * bulb_distance(position + offset.xyy).x
+            + offset.yyx * bulb_distance(position + offset.yyx).x
+            + offset.yxy * bulb_distance(position + offset.yxy).x
+            + offset.xxx * bulb_distance(position + offset.xxx).x,
+    );
+}
+
+// Distances along the ray where it enters and leaves the bounding sphere,
+// the entry after the exit when it misses
+fn bounding_interval(origin: vec3<f32>, direction: vec3<f32>) -> vec2<f32> {
+    let b = dot(origin, direction);
+    let discriminant = b * b - dot(origin, origin) + BOUNDING_RADIUS * BOUNDING_RADIUS;
+    if discriminant < 0.0 {
+        return vec2(1.0, 0.0);
+    }
+
+    let root = sqrt(discriminant);
+
+    return vec2(max(-b - root, 0.0), -b + root);
+}
+
+// How much of the light reaches the point, darkened by the surfaces the ray
+// toward it passes close to
+fn soft_shadow(origin: vec3<f32>, direction: vec3<f32>, epsilon: f32) -> f32 {
+    let interval = bounding_interval(origin, direction);
+
+    var light = 1.0;
+    var t = 4.0 * epsilon;
+    for (var i = 0; i < SHADOW_STEPS && t < interval.y; i++) {
+        let distance = bulb_distance(origin + direction * t).x;
+        if distance < epsilon {
+            return 0.0;
+        }
+
+        light = min(light, SHADOW_SHARPNESS * distance / t);
+        t += distance;
+    }
+
+    return clamp(light, 0.0, 1.0);
+}
+
+// Darkens creases, where the surface is closer than the distance from it
+// along the normal
+fn ambient_occlusion(position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    var occlusion = 0.0;
+    var weight = 1.0;
+    for (var i = 1; i <= OCCLUSION_SAMPLES; i++) {
+        let height = 0.02 * f32(i * i);
+        occlusion += weight * (height - bulb_distance(position + normal * height).x);
+        weight *= 0.6;
+    }
+
+    return clamp(1.0 - 3.0 * occlusion, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) in: vec4<f32>) -> @location(0) vec4<f32> {
+    // Camera orbiting the origin, y up
+    let eye = params.distance * vec3(
+        cos(params.pitch) * sin(params.yaw),
+        sin(params.pitch),
+        cos(params.pitch) * cos(params.yaw),
+    );
+    let forward = normalize(-eye);
+    let right = normalize(cross(forward, vec3(0.0, 1.0, 0.0)));
+    let up = cross(right, forward);
+
+    let screen = vec2(
+        (in.x / viewport.half_viewport_x - 1.0) * viewport.ratio,
+        1.0 - in.y / viewport.half_viewport_y,
+    );
+    let direction = normalize(forward * FOCAL_LENGTH + right * screen.x + up * screen.y);
+
+    // Half a pixel at unit distance, the surface is hit within a pixel
+    let pixel = 0.5 / (viewport.half_viewport_y * FOCAL_LENGTH);
+    let fog_color = hsl_to_rgb(vec3(color_params.hue, color_params.saturation, 0.3 * color_params.lightness));
+
+    let interval = bounding_interval(eye, direction);
+    var t = interval.x;
+    var hit = false;
+    var trap = 0.0;
+    for (var i = 0; i < MAX_STEPS && t < interval.y; i++) {
+        let estimate = bulb_distance(eye + direction * t);
+        if estimate.x < pixel * t {
+            hit = true;
+            trap = estimate.y;
+            break;
+        }
+
+        t += estimate.x;
+    }
+
+    if !hit {
+        return vec4(fog_color, 1.0);
+    }
+
+    let position = eye + direction * t;
+    let epsilon = pixel * t;
+    let normal = bulb_normal(position, epsilon);
+    let light = normalize(up - 0.5 * right - 0.3 * forward);
+
+    var diffuse = max(dot(normal, light), 0.0);
+    if params.soft_shadows > 0 && diffuse > 0.0 {
+        diffuse *= soft_shadow(position + normal * epsilon, light, epsilon);
+    }
+
+    var ambient = AMBIENT;
+    if params.ambient_occlusion > 0 {
+        ambient *= ambient_occlusion(position, normal);
+    }
+
+    // Orbits that came close to the origin are in [0, 1)
+    let surface = hsl_color(clamp(sqrt(trap), 0.0, 0.999), color_params);
+    let color = surface * (ambient + (1.0 - AMBIENT) * diffuse);
+
+    let fog = 1.0 - exp(-params.fog * t);
+
+    return vec4(mix(color, fog_color, fog), 1.0);
+}
@@ -0,0 +1,221 @@
+use std::{error::Error, fmt};
+
+use crate::{decimal::ParseDecimalError, params::MAX_ROOTS};
+
+type Complex = (f64, f64);
+
+// Durand-Kerner stops once no root moves more than this
+const ROOT_TOLERANCE: f64 = 1e-12;
+const MAX_ROOT_ITERATIONS: usize = 1000;
+
+/// Parses comma separated complex numbers written `<real> [imag]`, like
+/// `1, -0.5 0.866, -0.5 -0.866`, as roots or coefficients are entered.
+pub fn parse_complex_list(s: &str) -> Result<Vec<Complex>, ParseDecimalError> {
+    if s.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+
+    s.split(',')
+        .map(|number| {
+            let parts = number
+                .split_whitespace()
+                .map(|part| match part.parse::<f64>() {
+                    // Rust also reads `inf`, `nan` and overflowing numbers
+                    Ok(value) if value.is_finite() => Ok(value),
+                    _ => Err(ParseDecimalError),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            match parts[..] {
+                [real] => Ok((real, 0.)),
+                [real, imag] => Ok((real, imag)),
+                _ => Err(ParseDecimalError),
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParsePolynomialError {
+    Number(ParseDecimalError),
+    /// More roots than the shader holds.
+    TooManyRoots,
+}
+
+impl fmt::Display for ParsePolynomialError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Number(error) => write!(f, "{error}"),
+            Self::TooManyRoots => write!(f, "at most {MAX_ROOTS} roots are drawn"),
+        }
+    }
+}
+
+impl Error for ParsePolynomialError {}
+
+impl From<ParseDecimalError> for ParsePolynomialError {
+    fn from(error: ParseDecimalError) -> Self {
+        Self::Number(error)
+    }
+}
+
+/// Roots of the polynomial entered as a list of complex numbers, either its
+/// roots or, with `coefficients`, its coefficients highest degree first.
+/// Polynomials of too high a degree are rejected before solving them.
+pub fn parse_roots(s: &str, coefficients: bool) -> Result<Vec<Complex>, ParsePolynomialError> {
+    let numbers = parse_complex_list(s)?;
+    let count = if coefficients {
+        let nonzero = numbers.iter().skip_while(|&&c| c == (0., 0.)).count();
+        nonzero.saturating_sub(1)
+    } else {
+        numbers.len()
+    };
+    if count > MAX_ROOTS {
+        return Err(ParsePolynomialError::TooManyRoots);
+    }
+
+    Ok(if coefficients {
+        roots(&numbers)
+    } else {
+        numbers
+    })
+}
+
+/// Roots of the polynomial with `coefficients`, highest degree first, found
+/// all at once with the Durand-Kerner method.
+pub fn roots(coefficients: &[Complex]) -> Vec<Complex> {
+    let coefficients = match coefficients.iter().position(|&c| c != (0., 0.)) {
+        Some(leading) => &coefficients[leading..],
+        None => return Vec::new(),
+    };
+
+    // Monic, so the polynomial is the product of (z - root)
+    let monic = coefficients
+        .iter()
+        .map(|&c| div(c, coefficients[0]))
+        .collect::<Vec<_>>();
+    let degree = monic.len() - 1;
+
+    // Distinct starting points, neither real nor roots of unity
+    let mut roots = (0..degree)
+        .scan((1., 0.), |power, _| {
+            *power = mul(*power, (0.4, 0.9));
+            Some(*power)
+        })
+        .collect::<Vec<_>>();
+
+    for _ in 0..MAX_ROOT_ITERATIONS {
+        let mut largest_step = 0f64;
+
+        for i in 0..degree {
+            let value = monic
+                .iter()
+                .fold((0., 0.), |value, &c| add(mul(value, roots[i]), c));
+            let others = (0..degree)
+                .filter(|&j| j != i)
+                .fold((1., 0.), |product, j| mul(product, sub(roots[i], roots[j])));
+
+            let step = div(value, others);
+            roots[i] = sub(roots[i], step);
+            largest_step = largest_step.max(step.0.hypot(step.1));
+        }
+
+        if !largest_step.is_finite() || largest_step < ROOT_TOLERANCE {
+            break;
+        }
+    }
+
+    roots
+}
+
+fn add(a: Complex, b: Complex) -> Complex {
+    (a.0 + b.0, a.1 + b.1)
+}
+
+fn sub(a: Complex, b: Complex) -> Complex {
+    (a.0 - b.0, a.1 - b.1)
+}
+
+fn mul(a: Complex, b: Complex) -> Complex {
+    (a.0 * b.0 - a.1 * b.1, a.0 * b.1 + a.1 * b.0)
+}
+
+fn div(a: Complex, b: Complex) -> Complex {
+    let norm_squared = b.0 * b.0 + b.1 * b.1;
+
+    (
+        (a.0 * b.0 + a.1 * b.1) / norm_squared,
+        (a.1 * b.0 - a.0 * b.1) / norm_squared,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Complex, b: Complex) {
+        assert!((a.0 - b.0).hypot(a.1 - b.1) < 1e-9, "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn parses_real_and_complex_numbers() {
+        assert_eq!(
+            parse_complex_list("1, -0.5 0.866, 2e1 -3"),
+            Ok(vec![(1., 0.), (-0.5, 0.866), (20., -3.)])
+        );
+        assert_eq!(parse_complex_list("  "), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn rejects_malformed_numbers() {
+        assert_eq!(parse_complex_list("1 2 3"), Err(ParseDecimalError));
+        assert_eq!(parse_complex_list("1,,2"), Err(ParseDecimalError));
+        assert_eq!(parse_complex_list("one"), Err(ParseDecimalError));
+        assert_eq!(parse_complex_list("1, inf"), Err(ParseDecimalError));
+        assert_eq!(parse_complex_list("nan 0"), Err(ParseDecimalError));
+        assert_eq!(parse_complex_list("1 1e400"), Err(ParseDecimalError));
+    }
+
+    #[test]
+    fn finds_the_roots_of_the_coefficients() {
+        // z^3 - 1
+        let mut roots = parse_roots("1, 0, 0, -1", true).unwrap();
+        roots.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        let half_root_three = 3f64.sqrt() / 2.;
+        assert_close(roots[0], (-0.5, -half_root_three));
+        assert_close(roots[1], (1., 0.));
+        assert_close(roots[2], (-0.5, half_root_three));
+    }
+
+    #[test]
+    fn ignores_leading_zero_coefficients() {
+        assert_eq!(
+            parse_roots("0, 0, 1, -2", true).map(|roots| roots.len()),
+            Ok(1)
+        );
+        assert_eq!(parse_roots("0, 0", true), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn bounds_the_number_of_roots() {
+        let roots = vec!["1"; MAX_ROOTS].join(", ");
+        assert_eq!(
+            parse_roots(&roots, false).map(|roots| roots.len()),
+            Ok(MAX_ROOTS)
+        );
+
+        let roots = vec!["1"; MAX_ROOTS + 1].join(", ");
+        assert_eq!(
+            parse_roots(&roots, false),
+            Err(ParsePolynomialError::TooManyRoots)
+        );
+
+        // Degree MAX_ROOTS + 1, rejected before solving it
+        let coefficients = vec!["1"; MAX_ROOTS + 2].join(", ");
+        assert_eq!(
+            parse_roots(&coefficients, true),
+            Err(ParsePolynomialError::TooManyRoots)
+        );
+    }
+}
@@ -1,4 +1,6 @@
-use std::ops::Deref;
+use std::{error::Error, fmt, ops::Deref, str::FromStr};
+
+use crate::{decimal::ParseDecimalError, fixed::Fixed, floatexp::FloatExp};
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
@@ -16,77 +18,386 @@ impl Viewport {
             ratio: window_size[0] / window_size[1],
         }
     }
+
+    pub fn get_size(&self) -> (usize, usize) {
+        (
+            (self.half_viewport_x * 2.) as usize,
+            (self.half_viewport_y * 2.) as usize,
+        )
+    }
+
+    /// Distance from `point` to the farthest corner of the view, in the
+    /// units of [`Viewport::relative`].
+    pub fn radius_from(&self, point: (f32, f32)) -> f32 {
+        [(-1., -1.), (-1., 1.), (1., -1.), (1., 1.)]
+            .into_iter()
+            .map(|(x, y): (f32, f32)| (x * self.ratio - point.0).hypot(y - point.1))
+            .fold(0., f32::max)
+    }
+
+    /// Maps a pixel position to the view, `[-ratio, ratio]` horizontally and
+    /// `[-1, 1]` vertically with y pointing up, like `transform_position`.
+    pub fn relative(&self, position: (f32, f32)) -> (f32, f32) {
+        (
+            (position.0 - self.half_viewport_x) / self.half_viewport_x * self.ratio,
+            (self.half_viewport_y - position.1) / self.half_viewport_y,
+        )
+    }
 }
 
-const ZOOM_FACTOR: f32 = 1.2;
+const ZOOM_FACTOR: f64 = 1.2;
+// Deepest zoom as a power of two, about 1e-1000; reference orbits get too
+// slow to compute past it
+const MIN_ZOOM_EXPONENT: i32 = -3300;
+const MAX_ZOOM: f64 = 4.;
+const DEFAULT_ZOOM: f64 = 1.5;
+// Room left around a Multibrot set in its default view
+const VIEW_MARGIN: f64 = 1.1;
+// Farthest the view center gets from the origin along either axis
+const CENTER_LIMIT: f64 = 4.;
+// Rates of the logistic map that keep x in [0, 1], the only ones the
+// Lyapunov fractal is drawn for
+const LYAPUNOV_RATES: (f64, f64) = (0., 4.);
+
+// Below these zooms the previous kernel runs out of precision
+const DOUBLE_FLOAT_ZOOM: f64 = 1e-4;
+const PERTURBATION_ZOOM: f64 = 1e-11;
+// f32 deltas, and their squares in the glitch test, underflow past this
+const PERTURBATION_FLOAT_EXP_ZOOM: f64 = 1e-18;
+
+// Extra bits kept in the center past the ones the zoom level needs
+const GUARD_BITS: u32 = 64;
+
+pub const MAX_ITERATIONS: i32 = 10000;
+
+pub const MIN_EXPONENT: f32 = 1.5;
+pub const MAX_EXPONENT: f32 = 8.;
 
+pub const MIN_GAMMA: f32 = 0.1;
+pub const MAX_GAMMA: f32 = 4.;
+
+pub const MIN_BULB_POWER: f32 = 2.;
+pub const MAX_BULB_POWER: f32 = 16.;
+
+pub const MIN_HEIGHT_SCALE: f32 = 0.1;
+pub const MAX_HEIGHT_SCALE: f32 = 4.;
+pub const MAX_CONTOURS: u32 = 50;
+// Exponents this close to an integer are taken as that integer
+const INTEGER_EXPONENT_TOLERANCE: f32 = 1e-3;
+// Escaping this far out makes the smooth iteration count accurate
+const SMOOTH_ESCAPE_RADIUS: f64 = 256.;
+// Orbits past this |z|^2 escape whatever the norm, like in the shader
+const NORM_OVERFLOW: f32 = 1e30;
+// Smallest escape radius of custom formulas, however low the bailout radius
+// is set. Nothing bounds their orbits, this leaves room for the usual
+// polynomial and trigonometric ones.
+const CUSTOM_ESCAPE_RADIUS: f64 = 10.;
+// Past these |z|, and |c|, the Magnet I and II steps grow |z| at least as
+// fast as (|z| - 2)^2 / 25 and (|z| - 12)^2 / 361, so it never comes back.
+// Both are also past |c| for every pixel the view can show.
+const MAGNET_1_ESCAPE_RADIUS: f64 = 30.;
+const MAGNET_2_ESCAPE_RADIUS: f64 = 400.;
+
+#[derive(Debug, Copy, Clone)]
 pub enum Zoom {
     In,
     Out,
 }
 
-#[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+/// Iteration kernel used by the shader, picked from the zoom level.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Kernel {
+    Single = 0,
+    DoubleFloat = 1,
+    Perturbation = 2,
+    PerturbationFloatExp = 3,
+}
+
+impl Kernel {
+    pub fn is_perturbation(self) -> bool {
+        matches!(self, Kernel::Perturbation | Kernel::PerturbationFloatExp)
+    }
+}
+
+/// Complex point at the center of the view, kept at the precision the zoom
+/// level requires, and zoom. Its text form, `<real> <imag> <zoom>`, parses
+/// back to the exact same coordinates.
+#[derive(Debug, Clone, PartialEq)]
 pub struct Coordinates {
-    real: f32,
-    imag: f32,
-    zoom: f32,
+    real: Fixed,
+    imag: Fixed,
+    zoom: FloatExp,
 }
 
 impl Default for Coordinates {
     fn default() -> Self {
-        Self {
-            real: 0.0,
-            imag: 0.0,
-            zoom: 1.5,
-        }
+        Formula::Mandelbrot.default_coordinates(2.)
     }
 }
 
+/// Radius of the disk holding the Multibrot set of `exponent`, which is also
+/// the smallest escape radius that works for it.
+pub fn multibrot_radius(exponent: f32) -> f64 {
+    2f64.powf(1. / (exponent as f64 - 1.))
+}
+
+fn precision_bits(zoom: FloatExp) -> u32 {
+    (-zoom.log2()).max(0.) as u32 + GUARD_BITS
+}
+
+fn split(value: f64) -> (f32, f32) {
+    let hi = value as f32;
+    let lo = (value - hi as f64) as f32;
+
+    (hi, lo)
+}
+
 impl Coordinates {
-    pub fn get_complex(&self) -> (f32, f32) {
-        (self.real, self.imag)
+    pub fn new(center: (f64, f64), zoom: f64) -> Self {
+        let zoom = FloatExp::from_f64(zoom.min(MAX_ZOOM));
+        let bits = precision_bits(zoom);
+
+        Self {
+            real: Fixed::from_f64(center.0, bits),
+            imag: Fixed::from_f64(center.1, bits),
+            zoom,
+        }
     }
 
-    pub fn set_complex(mut self, complex: (f32, f32)) -> Self {
-        self.real = complex.0;
-        self.imag = complex.1;
+    /// Default view of Julia sets, centered on the origin they are symmetric
+    /// around.
+    pub fn julia(exponent: f32) -> Self {
+        if exponent == 2. {
+            Self::new((0., 0.), DEFAULT_ZOOM)
+        } else {
+            Self::new((0., 0.), multibrot_radius(exponent) * VIEW_MARGIN)
+        }
+    }
 
-        self.real = self.real.clamp(-2., 2.);
-        self.imag = self.imag.clamp(-2., 2.);
+    pub fn get_center(&self) -> (&Fixed, &Fixed) {
+        (&self.real, &self.imag)
+    }
+
+    pub fn get_complex(&self) -> (f64, f64) {
+        (self.real.to_f64(), self.imag.to_f64())
+    }
+
+    /// Complex point at `position`, in the units of [`Viewport::relative`].
+    pub fn point_at(&self, position: (f32, f32)) -> (f64, f64) {
+        (
+            self.real
+                .add_float_exp(self.zoom.mul_f64(position.0 as f64))
+                .to_f64(),
+            self.imag
+                .add_float_exp(self.zoom.mul_f64(position.1 as f64))
+                .to_f64(),
+        )
+    }
+
+    /// Whether the center lies within `(min, max)` on both axes.
+    pub fn center_within(&self, (min, max): (f64, f64)) -> bool {
+        [&self.real, &self.imag]
+            .iter()
+            .all(|value| (min..=max).contains(&value.to_f64()))
+    }
+
+    /// Moves the center within `(min, max)` on both axes.
+    pub fn clamp_center(mut self, (min, max): (f64, f64)) -> Self {
+        self.real = self.real.clamp(min, max);
+        self.imag = self.imag.clamp(min, max);
 
         self
     }
 
-    pub fn get_zoom(&self) -> f32 {
+    pub fn translate(mut self, offset: (FloatExp, FloatExp)) -> Self {
+        self.real = self
+            .real
+            .add_float_exp(offset.0)
+            .clamp(-CENTER_LIMIT, CENTER_LIMIT);
+        self.imag = self
+            .imag
+            .add_float_exp(offset.1)
+            .clamp(-CENTER_LIMIT, CENTER_LIMIT);
+
+        self
+    }
+
+    pub fn get_zoom(&self) -> FloatExp {
         self.zoom
     }
 
-    pub fn set_zoom(mut self, zoom: Zoom, zoom_center: Option<(f32, f32)>) -> Self {
-        let old_zoom = self.zoom;
+    /// Zooms in or out one step, stopping at `min_zoom`, the deepest zoom
+    /// the kernels drawing the view can resolve, see
+    /// [`FractalParams::min_zoom`].
+    pub fn set_zoom(
+        mut self,
+        zoom: Zoom,
+        zoom_center: Option<(f32, f32)>,
+        min_zoom: FloatExp,
+    ) -> Self {
+        let old_zoom = self.get_zoom();
 
         let factor = match zoom {
             Zoom::In => 1. / ZOOM_FACTOR,
             Zoom::Out => ZOOM_FACTOR,
         };
-        self.zoom *= factor;
-
-        self.zoom = self.zoom.clamp(0.00001, 1.5);
+        self.zoom = self.zoom.mul_f64(factor);
+        self = self.clamp_zoom(min_zoom);
 
         if let Some(zoom_center) = zoom_center {
-            let old_scaled_rel_x = zoom_center.0 * old_zoom;
-            let old_scaled_rel_y = zoom_center.1 * old_zoom;
-            let new_scaled_rel_x = zoom_center.0 * self.zoom;
-            let new_scaled_rel_y = zoom_center.1 * self.zoom;
-
-            let new_real = self.real + (old_scaled_rel_x - new_scaled_rel_x);
-            let new_imag = self.imag - (old_scaled_rel_y - new_scaled_rel_y);
+            let zoom_change = old_zoom - self.get_zoom();
 
-            self.set_complex((new_real, new_imag))
+            self.translate((
+                zoom_change.mul_f64(zoom_center.0 as f64),
+                zoom_change.mul_f64(zoom_center.1 as f64),
+            ))
         } else {
             self
         }
     }
+
+    /// Keeps the zoom between `min_zoom` and the widest view, with the
+    /// precision of the center following it.
+    pub fn clamp_zoom(mut self, min_zoom: FloatExp) -> Self {
+        let deepest = FloatExp::new(1., MIN_ZOOM_EXPONENT);
+        let min_zoom = if min_zoom < deepest {
+            deepest
+        } else {
+            min_zoom
+        };
+        if self.zoom < min_zoom {
+            self.zoom = min_zoom;
+        } else if self.zoom > FloatExp::from_f64(MAX_ZOOM) {
+            self.zoom = FloatExp::from_f64(MAX_ZOOM);
+        }
+
+        let bits = precision_bits(self.zoom);
+        self.real = self.real.with_bits(bits);
+        self.imag = self.imag.with_bits(bits);
+
+        self
+    }
+
+    pub fn kernel(&self) -> Kernel {
+        if self.zoom < FloatExp::from_f64(PERTURBATION_FLOAT_EXP_ZOOM) {
+            Kernel::PerturbationFloatExp
+        } else if self.zoom < FloatExp::from_f64(PERTURBATION_ZOOM) {
+            Kernel::Perturbation
+        } else if self.zoom < FloatExp::from_f64(DOUBLE_FLOAT_ZOOM) {
+            Kernel::DoubleFloat
+        } else {
+            Kernel::Single
+        }
+    }
+
+    /// `kernel` is usually [`Coordinates::kernel`], unless the fractal has no
+    /// deep zoom support.
+    pub fn to_uniform(&self, kernel: Kernel) -> CoordinatesUniform {
+        let (real, imag) = self.get_complex();
+        let (real, real_lo) = split(real);
+        let (imag, imag_lo) = split(imag);
+        let (zoom, zoom_lo) = split(self.zoom.to_f64());
+        let (zoom_mantissa, zoom_exponent) = self.zoom.to_parts();
+
+        CoordinatesUniform {
+            real,
+            real_lo,
+            imag,
+            imag_lo,
+            zoom,
+            zoom_lo,
+            zoom_mantissa: zoom_mantissa as f32,
+            zoom_exponent,
+            kernel: kernel as i32,
+        }
+    }
+}
+
+impl fmt::Display for Coordinates {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.real, self.imag, self.zoom)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseCoordinatesError {
+    /// Not the three parts `<real> <imag> <zoom>`.
+    Format,
+    Number(ParseDecimalError),
+    ZoomOutOfRange,
+    /// Outside the `(min, max)` range of the formula on either axis.
+    CenterOutOfRange((f64, f64)),
+}
+
+impl fmt::Display for ParseCoordinatesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Format => write!(f, "expected <real> <imag> <zoom>"),
+            Self::Number(error) => write!(f, "{error}"),
+            Self::ZoomOutOfRange => write!(
+                f,
+                "zoom must be between 2^{MIN_ZOOM_EXPONENT} and {MAX_ZOOM}"
+            ),
+            Self::CenterOutOfRange((min, max)) => {
+                write!(f, "center must be between {min} and {max} on both axes")
+            }
+        }
+    }
+}
+
+impl Error for ParseCoordinatesError {}
+
+impl From<ParseDecimalError> for ParseCoordinatesError {
+    fn from(error: ParseDecimalError) -> Self {
+        Self::Number(error)
+    }
+}
+
+impl FromStr for Coordinates {
+    type Err = ParseCoordinatesError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s.split_whitespace().collect::<Vec<_>>();
+        let [real, imag, zoom] = parts[..] else {
+            return Err(ParseCoordinatesError::Format);
+        };
+
+        let zoom = zoom.parse::<FloatExp>()?;
+        if zoom < FloatExp::new(1., MIN_ZOOM_EXPONENT) || zoom > FloatExp::from_f64(MAX_ZOOM) {
+            return Err(ParseCoordinatesError::ZoomOutOfRange);
+        }
+        let bits = precision_bits(zoom);
+
+        let coordinates = Self {
+            real: real.parse::<Fixed>()?.with_bits(bits),
+            imag: imag.parse::<Fixed>()?.with_bits(bits),
+            zoom,
+        };
+        let range = (-CENTER_LIMIT, CENTER_LIMIT);
+        if !coordinates.center_within(range) {
+            return Err(ParseCoordinatesError::CenterOutOfRange(range));
+        }
+
+        Ok(coordinates)
+    }
+}
+
+/// GPU side of [`Coordinates`]: center and zoom as double-floats, an `f64`
+/// split into an `f32` high part and the `f32` remainder, so the shader can
+/// rebuild the full precision without native `f64` support. The zoom is also
+/// given as a mantissa and a power of two for views deeper than `f32` reaches.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct CoordinatesUniform {
+    real: f32,
+    real_lo: f32,
+    imag: f32,
+    imag_lo: f32,
+    zoom: f32,
+    zoom_lo: f32,
+    zoom_mantissa: f32,
+    zoom_exponent: i32,
+    kernel: i32,
 }
 
 #[repr(C)]
@@ -102,7 +413,64 @@ impl Deref for Iterations {
 
 impl Iterations {
     pub fn new(value: i32) -> Self {
-        Self(value.clamp(0, 10000))
+        Self(value.clamp(0, MAX_ITERATIONS))
+    }
+}
+
+/// What escaped pixels are coloured by. The distance to the set is only
+/// estimated for the Mandelbrot formula, pixels of the others keep the
+/// iteration colours in the distance modes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Coloring {
+    Iterations = 0,
+    /// Grey from black at the boundary of the set to white a few pixels
+    /// away, which draws its filaments sharply.
+    Distance = 1,
+    /// The iteration colours, darkened by the distance grey.
+    DistanceMix = 2,
+    /// How close the orbit came to the shape in [`TrapParams`], pixels
+    /// inside the set included.
+    OrbitTrap = 3,
+    /// Average over the orbit of `sin(density * arg z)`, which draws stripes
+    /// along the filaments.
+    StripeAverage = 4,
+    /// Average over the orbit of where `|z|` falls between the bounds the
+    /// triangle inequality puts on it, repeated density times.
+    TriangleInequality = 5,
+}
+
+impl Coloring {
+    pub const ALL: [Coloring; 6] = [
+        Coloring::Iterations,
+        Coloring::Distance,
+        Coloring::DistanceMix,
+        Coloring::OrbitTrap,
+        Coloring::StripeAverage,
+        Coloring::TriangleInequality,
+    ];
+
+    /// Whether it is built from every point of the orbit, so no iteration
+    /// can be skipped by the series approximation.
+    pub fn needs_whole_orbit(self) -> bool {
+        matches!(
+            self,
+            Coloring::OrbitTrap | Coloring::StripeAverage | Coloring::TriangleInequality
+        )
+    }
+}
+
+impl fmt::Display for Coloring {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Coloring::Iterations => "Iterations",
+            Coloring::Distance => "Distance",
+            Coloring::DistanceMix => "Distance mix",
+            Coloring::OrbitTrap => "Orbit trap",
+            Coloring::StripeAverage => "Stripe average",
+            Coloring::TriangleInequality => "Triangle inequality",
+        };
+
+        write!(f, "{name}")
     }
 }
 
@@ -115,6 +483,11 @@ pub struct ColorParams {
     hue_linked: i32,
     saturation_linked: i32,
     lightness_linked: i32,
+    smooth_color: i32,
+    coloring: i32,
+    interior_overlay: i32,
+    stripe_density: f32,
+    triangle_density: f32,
 }
 
 impl ColorParams {
@@ -126,6 +499,11 @@ impl ColorParams {
             hue_linked: 0,
             saturation_linked: 0,
             lightness_linked: 0,
+            smooth_color: 0,
+            coloring: Coloring::Iterations as i32,
+            interior_overlay: 0,
+            stripe_density: 5.,
+            triangle_density: 1.,
         }
     }
 
@@ -156,4 +534,1177 @@ impl ColorParams {
 
         self
     }
+
+    /// Whether colours follow the continuous iteration count rather than the
+    /// whole one.
+    pub fn is_smooth(&self) -> bool {
+        self.smooth_color > 0
+    }
+
+    pub fn set_smooth(mut self, value: bool) -> Self {
+        self.smooth_color = value as i32;
+
+        self
+    }
+
+    pub fn get_coloring(&self) -> Coloring {
+        Coloring::ALL[self.coloring as usize]
+    }
+
+    pub fn set_coloring(mut self, value: Coloring) -> Self {
+        self.coloring = value as i32;
+
+        self
+    }
+
+    /// Whether pixels found inside the set without iterating all the way are
+    /// tinted by the test that found them, for debugging.
+    pub fn has_interior_overlay(&self) -> bool {
+        self.interior_overlay > 0
+    }
+
+    pub fn set_interior_overlay(mut self, value: bool) -> Self {
+        self.interior_overlay = value as i32;
+
+        self
+    }
+
+    pub fn get_stripe_density(&self) -> f32 {
+        self.stripe_density
+    }
+
+    /// Rounded to a whole number of stripes, as the stripes of other
+    /// densities break along the negative real axis, where the argument of z
+    /// wraps around.
+    pub fn set_stripe_density(mut self, value: f32) -> Self {
+        self.stripe_density = value.round().max(1.);
+
+        self
+    }
+
+    pub fn get_triangle_density(&self) -> f32 {
+        self.triangle_density
+    }
+
+    /// At least one band per colour cycle like the stripes, but not
+    /// rounded, the bands following the smooth average rather than the
+    /// argument of z.
+    pub fn set_triangle_density(mut self, value: f32) -> Self {
+        self.triangle_density = value.max(1.);
+
+        self
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TrapShape {
+    Point = 0,
+    /// Horizontal line through the center.
+    Line = 1,
+    /// Horizontal and vertical lines through the center.
+    Cross = 2,
+    Circle = 3,
+}
+
+impl TrapShape {
+    pub const ALL: [TrapShape; 4] = [
+        TrapShape::Point,
+        TrapShape::Line,
+        TrapShape::Cross,
+        TrapShape::Circle,
+    ];
+}
+
+impl fmt::Display for TrapShape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TrapShape::Point => "Point",
+            TrapShape::Line => "Line",
+            TrapShape::Cross => "Cross",
+            TrapShape::Circle => "Circle",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+/// Orbit trap of the [`Coloring::OrbitTrap`] colouring. Pixels are coloured
+/// by the smallest distance between their orbit and the shape, over the
+/// size, which is also the radius of the circle.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TrapParams {
+    shape: i32,
+    real: f32,
+    imag: f32,
+    size: f32,
+}
+
+impl Default for TrapParams {
+    fn default() -> Self {
+        Self::new(TrapShape::Point, (0., 0.), 0.5)
+    }
+}
+
+impl TrapParams {
+    pub fn new(shape: TrapShape, center: (f32, f32), size: f32) -> Self {
+        Self {
+            shape: shape as i32,
+            real: center.0,
+            imag: center.1,
+            size,
+        }
+    }
+}
+
+/// Directional light over the exterior, shading it as a surface rising away
+/// from the set along the derivative of the orbit. Angles are in degrees.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightParams {
+    enabled: i32,
+    azimuth: f32,
+    elevation: f32,
+    intensity: f32,
+}
+
+impl Default for LightParams {
+    fn default() -> Self {
+        Self::new(false, (45., 45., 1.))
+    }
+}
+
+impl LightParams {
+    /// The light is `(azimuth, elevation, intensity)`, the intensity blending
+    /// between the unlit colours at 0 and fully shaded ones at 1.
+    pub fn new(enabled: bool, light: (f32, f32, f32)) -> Self {
+        Self {
+            enabled: enabled as i32,
+            azimuth: light.0,
+            elevation: light.1.clamp(0., 90.),
+            intensity: light.2.clamp(0., 1.),
+        }
+    }
+}
+
+/// Iteration formula, `z^2 + c` with some components of `z` or of its square
+/// replaced by their absolute value or negated, or Newton's method on the
+/// polynomial with the roots in [`NewtonParams`], or a formula typed by the
+/// user and compiled into the shader, or the Lyapunov exponent of the
+/// logistic map with the sequence in [`LyapunovParams`]. Phoenix, Magnet and
+/// Nova are the rational and two-step families, whose orbits can converge
+/// instead of escaping, and sine, cosine, exponential and lambda multiply a
+/// function of `z` by `c`. A hybrid cycles through the steps in
+/// [`HybridParams`]. Only the Mandelbrot formula uses the exponent.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Formula {
+    Mandelbrot = 0,
+    BurningShip = 1,
+    Tricorn = 2,
+    Celtic = 3,
+    PerpendicularMandelbrot = 4,
+    PerpendicularBurningShip = 5,
+    PerpendicularCeltic = 6,
+    Newton = 7,
+    Custom = 8,
+    Lyapunov = 9,
+    Phoenix = 10,
+    MagnetI = 11,
+    MagnetII = 12,
+    Nova = 13,
+    Sine = 14,
+    Cosine = 15,
+    Exponential = 16,
+    Lambda = 17,
+    Hybrid = 18,
+}
+
+impl Formula {
+    pub const ALL: [Formula; 19] = [
+        Formula::Mandelbrot,
+        Formula::BurningShip,
+        Formula::Tricorn,
+        Formula::Celtic,
+        Formula::PerpendicularMandelbrot,
+        Formula::PerpendicularBurningShip,
+        Formula::PerpendicularCeltic,
+        Formula::Newton,
+        Formula::Custom,
+        Formula::Lyapunov,
+        Formula::Phoenix,
+        Formula::MagnetI,
+        Formula::MagnetII,
+        Formula::Nova,
+        Formula::Sine,
+        Formula::Cosine,
+        Formula::Exponential,
+        Formula::Lambda,
+        Formula::Hybrid,
+    ];
+
+    /// View framing the whole set. The Burning Ship is drawn upright, which
+    /// puts most of it above the real axis.
+    pub fn default_coordinates(self, exponent: f32) -> Coordinates {
+        match self {
+            Formula::Mandelbrot if exponent != 2. => {
+                Coordinates::new((0., 0.), multibrot_radius(exponent) * VIEW_MARGIN)
+            }
+            Formula::Mandelbrot => Coordinates::new((-0.4, 0.), DEFAULT_ZOOM),
+            Formula::BurningShip => Coordinates::new((-0.5, 0.55), 1.3),
+            Formula::Tricorn => Coordinates::new((-0.55, 0.), 1.8),
+            Formula::Celtic => Coordinates::new((-0.85, 0.), 1.8),
+            Formula::PerpendicularMandelbrot => Coordinates::new((-0.65, 0.), 1.3),
+            Formula::PerpendicularBurningShip => Coordinates::new((-0.5, 0.1), 1.3),
+            Formula::PerpendicularCeltic => Coordinates::new((-0.85, 0.), 1.5),
+            Formula::Newton
+            | Formula::Custom
+            | Formula::Phoenix
+            | Formula::Nova
+            | Formula::Hybrid => Coordinates::new((0., 0.), DEFAULT_ZOOM),
+            Formula::MagnetI => Coordinates::new((1.5, 0.), 2.5),
+            Formula::MagnetII => Coordinates::new((1., 0.), 2.),
+            Formula::Sine | Formula::Cosine => Coordinates::new((0., 0.), 3.),
+            Formula::Exponential => Coordinates::new((-1., 0.), 3.),
+            Formula::Lambda => Coordinates::new((1., 0.), 3.),
+            // Real is the rate a, imag the rate b, both in [2, 4]
+            Formula::Lyapunov => Coordinates::new((3., 3.), 1.),
+        }
+    }
+
+    /// Range the view center stays in on both axes, the rates the Lyapunov
+    /// fractal is drawn for, or around the origin for the other formulas.
+    pub fn center_range(self) -> (f64, f64) {
+        match self {
+            Formula::Lyapunov => LYAPUNOV_RATES,
+            _ => (-CENTER_LIMIT, CENTER_LIMIT),
+        }
+    }
+
+    /// Coordinates parsed from `s`, with the center in range of the formula.
+    pub fn parse_coordinates(self, s: &str) -> Result<Coordinates, ParseCoordinatesError> {
+        let coordinates = s.parse::<Coordinates>()?;
+        let range = self.center_range();
+        if !coordinates.center_within(range) {
+            return Err(ParseCoordinatesError::CenterOutOfRange(range));
+        }
+
+        Ok(coordinates)
+    }
+
+    /// Whether the double-float and perturbation kernels can draw it.
+    pub fn has_deep_zoom(self) -> bool {
+        matches!(
+            self,
+            Formula::Mandelbrot
+                | Formula::BurningShip
+                | Formula::Tricorn
+                | Formula::Celtic
+                | Formula::PerpendicularMandelbrot
+                | Formula::PerpendicularBurningShip
+                | Formula::PerpendicularCeltic
+                | Formula::Hybrid
+        )
+    }
+
+    /// Whether its orbits escape past the bailout radius, in the norm picked.
+    /// The sine, cosine and exponential have their own test.
+    pub fn has_bailout_radius(self) -> bool {
+        !matches!(
+            self,
+            Formula::Newton
+                | Formula::Lyapunov
+                | Formula::Sine
+                | Formula::Cosine
+                | Formula::Exponential
+        )
+    }
+
+    /// Whether it adds a `c` that can be fixed for a Julia set.
+    pub fn has_julia(self) -> bool {
+        !matches!(self, Formula::Newton | Formula::Lyapunov)
+    }
+}
+
+impl fmt::Display for Formula {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Formula::Mandelbrot => "Mandelbrot",
+            Formula::BurningShip => "Burning Ship",
+            Formula::Tricorn => "Tricorn",
+            Formula::Celtic => "Celtic",
+            Formula::PerpendicularMandelbrot => "Perpendicular Mandelbrot",
+            Formula::PerpendicularBurningShip => "Perpendicular Burning Ship",
+            Formula::PerpendicularCeltic => "Perpendicular Celtic",
+            Formula::Newton => "Newton",
+            Formula::Custom => "Custom",
+            Formula::Lyapunov => "Lyapunov",
+            Formula::Phoenix => "Phoenix",
+            Formula::MagnetI => "Magnet I",
+            Formula::MagnetII => "Magnet II",
+            Formula::Nova => "Nova",
+            Formula::Sine => "Sine",
+            Formula::Cosine => "Cosine",
+            Formula::Exponential => "Exponential",
+            Formula::Lambda => "Lambda",
+            Formula::Hybrid => "Hybrid",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+/// Norm of `z` the escape radius is measured in. Only the Euclidean one
+/// gives round level sets, the others draw the bands around the set in other
+/// shapes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BailoutNorm {
+    Euclidean = 0,
+    Maximum = 1,
+    Real = 2,
+    Imaginary = 3,
+    Manhattan = 4,
+}
+
+impl BailoutNorm {
+    pub const ALL: [BailoutNorm; 5] = [
+        BailoutNorm::Euclidean,
+        BailoutNorm::Maximum,
+        BailoutNorm::Real,
+        BailoutNorm::Imaginary,
+        BailoutNorm::Manhattan,
+    ];
+
+    fn norm(self, z: [f32; 2]) -> f32 {
+        match self {
+            BailoutNorm::Euclidean => z[0].hypot(z[1]),
+            BailoutNorm::Maximum => z[0].abs().max(z[1].abs()),
+            BailoutNorm::Real => z[0].abs(),
+            BailoutNorm::Imaginary => z[1].abs(),
+            BailoutNorm::Manhattan => z[0].abs() + z[1].abs(),
+        }
+    }
+}
+
+impl fmt::Display for BailoutNorm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            BailoutNorm::Euclidean => "Euclidean",
+            BailoutNorm::Maximum => "Maximum",
+            BailoutNorm::Real => "|Re|",
+            BailoutNorm::Imaginary => "|Im|",
+            BailoutNorm::Manhattan => "Manhattan",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+/// Which set is drawn, the formula iterated until the norm of `z` passes
+/// `escape_radius`. In Julia mode every pixel starts the iteration at its own
+/// position and adds the same `c`, picked from the Mandelbrot set.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct FractalParams {
+    julia: i32,
+    julia_real: f32,
+    julia_real_lo: f32,
+    julia_imag: f32,
+    julia_imag_lo: f32,
+    exponent: f32,
+    escape_radius: f32,
+    formula: i32,
+    bailout_radius: f32,
+    bailout_norm: i32,
+    phoenix_real: f32,
+    phoenix_imag: f32,
+    relaxation: f32,
+}
+
+impl FractalParams {
+    pub fn new() -> Self {
+        Self {
+            julia: 0,
+            julia_real: 0.,
+            julia_real_lo: 0.,
+            julia_imag: 0.,
+            julia_imag_lo: 0.,
+            exponent: 2.,
+            escape_radius: SMOOTH_ESCAPE_RADIUS as f32,
+            formula: Formula::Mandelbrot as i32,
+            bailout_radius: SMOOTH_ESCAPE_RADIUS as f32,
+            bailout_norm: BailoutNorm::Euclidean as i32,
+            phoenix_real: -0.5,
+            phoenix_imag: 0.,
+            relaxation: 1.,
+        }
+    }
+
+    pub fn get_formula(&self) -> Formula {
+        Formula::ALL[self.formula as usize]
+    }
+
+    pub fn set_formula(mut self, value: Formula) -> Self {
+        self.formula = value as i32;
+
+        self.with_escape_radius()
+    }
+
+    pub fn is_julia(&self) -> bool {
+        self.julia > 0
+    }
+
+    pub fn set_julia(mut self, value: bool) -> Self {
+        self.julia = value as i32;
+
+        self.with_escape_radius()
+    }
+
+    pub fn get_julia_constant(&self) -> (f64, f64) {
+        (
+            self.julia_real as f64 + self.julia_real_lo as f64,
+            self.julia_imag as f64 + self.julia_imag_lo as f64,
+        )
+    }
+
+    pub fn set_julia_constant(mut self, value: (f64, f64)) -> Self {
+        (self.julia_real, self.julia_real_lo) = split(value.0);
+        (self.julia_imag, self.julia_imag_lo) = split(value.1);
+
+        self.with_escape_radius()
+    }
+
+    /// The Julia constant when a Julia set is drawn.
+    pub fn get_julia(&self) -> Option<(f64, f64)> {
+        self.is_julia().then(|| self.get_julia_constant())
+    }
+
+    pub fn get_exponent(&self) -> f32 {
+        self.exponent
+    }
+
+    /// Power `z` is raised to by the formula, 2 for all but the Mandelbrot
+    /// one.
+    pub fn get_power(&self) -> f32 {
+        match self.get_formula() {
+            Formula::Mandelbrot => self.exponent,
+            _ => 2.,
+        }
+    }
+
+    /// The power when it is an integer, which the deep zoom kernels need.
+    pub fn get_integer_power(&self) -> Option<u32> {
+        let power = self.get_power();
+
+        (power.fract() == 0.).then_some(power as u32)
+    }
+
+    /// Whether the view can zoom past `f32` precision, which needs both
+    /// the formula and its power to be supported by the deep zoom kernels.
+    pub fn has_deep_zoom(&self) -> bool {
+        self.get_formula().has_deep_zoom() && self.get_integer_power().is_some()
+    }
+
+    /// Deepest zoom the kernels drawing these params resolve pixels at.
+    /// Without the deep zoom kernels, `f32` runs out of precision where
+    /// the double-float kernel would take over, and without `perturbation`
+    /// the double-float kernel runs out where the perturbation ones would.
+    pub fn min_zoom(&self, perturbation: bool) -> FloatExp {
+        if !self.has_deep_zoom() {
+            FloatExp::from_f64(DOUBLE_FLOAT_ZOOM)
+        } else if !perturbation {
+            FloatExp::from_f64(PERTURBATION_ZOOM)
+        } else {
+            FloatExp::new(1., MIN_ZOOM_EXPONENT)
+        }
+    }
+
+    pub fn set_exponent(mut self, value: f32) -> Self {
+        let value = value.clamp(MIN_EXPONENT, MAX_EXPONENT);
+        self.exponent = if (value - value.round()).abs() < INTEGER_EXPONENT_TOLERANCE {
+            value.round()
+        } else {
+            value
+        };
+
+        self.with_escape_radius()
+    }
+
+    /// `p` of the Phoenix formula, `z^2 + c + p` times the point before `z`.
+    pub fn get_phoenix(&self) -> (f32, f32) {
+        (self.phoenix_real, self.phoenix_imag)
+    }
+
+    pub fn set_phoenix(mut self, value: (f32, f32)) -> Self {
+        (self.phoenix_real, self.phoenix_imag) = value;
+
+        self.with_escape_radius()
+    }
+
+    /// Step size of the Newton iteration in the Nova formula, 1 for plain
+    /// Newton's method.
+    pub fn get_relaxation(&self) -> f32 {
+        self.relaxation
+    }
+
+    pub fn set_relaxation(mut self, value: f32) -> Self {
+        self.relaxation = value;
+
+        self
+    }
+
+    pub fn get_bailout_radius(&self) -> f32 {
+        self.bailout_radius
+    }
+
+    /// Radius asked for, used unless it is too small for the orbits past it
+    /// to be sure to escape.
+    pub fn set_bailout_radius(mut self, value: f32) -> Self {
+        self.bailout_radius = value;
+
+        self.with_escape_radius()
+    }
+
+    pub fn get_bailout_norm(&self) -> BailoutNorm {
+        BailoutNorm::ALL[self.bailout_norm as usize]
+    }
+
+    pub fn set_bailout_norm(mut self, value: BailoutNorm) -> Self {
+        self.bailout_norm = value as i32;
+
+        self
+    }
+
+    /// Whether `z` is past the escape radius, like `escapes` in the shader.
+    pub fn escapes(&self, z: [f32; 2]) -> bool {
+        let norm = self.get_bailout_norm().norm(z);
+
+        norm > self.escape_radius || z[0] * z[0] + z[1] * z[1] > NORM_OVERFLOW
+    }
+
+    // Orbits can't come back once past both the set and `c`. The Phoenix
+    // step takes at most |p| times the smaller point before, so it grows
+    // |z| past 2 + |p|. Nova orbits shrink by |1 - relaxation / 3| far out
+    // and only leave through the overflow test. The lambda step grows |z|
+    // past 1 + 1 / |λ|, where the λ that escape have |λ| > 1, the orbits for
+    // the ones inside the unit disk falling to 0.
+    fn with_escape_radius(mut self) -> Self {
+        let mut radius = match self.get_formula() {
+            Formula::Custom => CUSTOM_ESCAPE_RADIUS,
+            Formula::Phoenix => 2. + (self.phoenix_real as f64).hypot(self.phoenix_imag as f64),
+            Formula::MagnetI => MAGNET_1_ESCAPE_RADIUS,
+            Formula::MagnetII => MAGNET_2_ESCAPE_RADIUS,
+            Formula::Nova => (NORM_OVERFLOW as f64).sqrt(),
+            Formula::Lambda => {
+                let lambda = self
+                    .get_julia()
+                    .map_or(1., |(real, imag)| real.hypot(imag).min(1.));
+
+                (1. + 1. / lambda).min((NORM_OVERFLOW as f64).sqrt())
+            }
+            _ => multibrot_radius(self.get_power()),
+        };
+        if let Some((real, imag)) = self.get_julia() {
+            radius = radius.max(real.hypot(imag));
+        }
+        self.escape_radius = radius.max(self.bailout_radius as f64) as f32;
+
+        self
+    }
+}
+
+pub const MAX_ROOTS: usize = 16;
+
+/// Roots of the polynomial the Newton formula finds, each pixel converging
+/// to one of them. Stored as `[real, imag, 0, 0]` because arrays in uniforms
+/// have a 16 byte stride.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct NewtonParams {
+    roots: [[f32; 4]; MAX_ROOTS],
+    root_count: i32,
+    _padding: [i32; 3],
+}
+
+impl Default for NewtonParams {
+    /// The cube roots of unity, roots of `z^3 - 1`.
+    fn default() -> Self {
+        let half_sqrt_3 = 3f64.sqrt() / 2.;
+
+        Self::new(&[(1., 0.), (-0.5, half_sqrt_3), (-0.5, -half_sqrt_3)])
+    }
+}
+
+impl NewtonParams {
+    /// Keeps the first [`MAX_ROOTS`] roots.
+    pub fn new(roots: &[(f64, f64)]) -> Self {
+        let mut params = Self {
+            roots: [[0.; 4]; MAX_ROOTS],
+            root_count: roots.len().min(MAX_ROOTS) as i32,
+            _padding: [0; 3],
+        };
+        for (slot, root) in params.roots.iter_mut().zip(roots) {
+            *slot = [root.0 as f32, root.1 as f32, 0., 0.];
+        }
+
+        params
+    }
+}
+
+pub const MAX_SEQUENCE_LENGTH: usize = 128;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseSequenceError;
+
+impl fmt::Display for ParseSequenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected 1 to {MAX_SEQUENCE_LENGTH} letters A or B")
+    }
+}
+
+impl Error for ParseSequenceError {}
+
+/// Sequence of the Lyapunov fractal, the order the logistic map
+/// `x = r x (1 - x)` takes its rate `r` from `a` and `b`, repeated. Stored
+/// one bit per step, set for `b`. Its text form is letters like `AABAB`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LyapunovParams {
+    sequence: [u32; MAX_SEQUENCE_LENGTH / 32],
+    length: i32,
+    _padding: [i32; 3],
+}
+
+impl Default for LyapunovParams {
+    fn default() -> Self {
+        "AB".parse().unwrap()
+    }
+}
+
+impl FromStr for LyapunovParams {
+    type Err = ParseSequenceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let steps = s
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| match c.to_ascii_uppercase() {
+                'A' => Ok(false),
+                'B' => Ok(true),
+                _ => Err(ParseSequenceError),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if steps.is_empty() || steps.len() > MAX_SEQUENCE_LENGTH {
+            return Err(ParseSequenceError);
+        }
+
+        let mut params = Self {
+            sequence: [0; MAX_SEQUENCE_LENGTH / 32],
+            length: steps.len() as i32,
+            _padding: [0; 3],
+        };
+        for (index, &b) in steps.iter().enumerate() {
+            params.sequence[index / 32] |= (b as u32) << (index % 32);
+        }
+
+        Ok(params)
+    }
+}
+
+pub const MAX_HYBRID_STEPS: usize = 32;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseHybridError;
+
+impl fmt::Display for ParseHybridError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "expected 1 to {MAX_HYBRID_STEPS} steps like \"2 Mandelbrot, Burning Ship\""
+        )
+    }
+}
+
+impl Error for ParseHybridError {}
+
+/// Steps of the hybrid formula, the formula each iteration uses, repeated.
+/// Only the formulas of the deep zoom kernels, which all raise `z` to a power
+/// and add `c`, can be steps, so hybrids zoom as deep as them. Its text form is comma separated formula names,
+/// each after an optional count, like `2 Mandelbrot, Burning Ship`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct HybridParams {
+    formulas: [[i32; 4]; MAX_HYBRID_STEPS / 4],
+    length: i32,
+    _padding: [i32; 3],
+}
+
+impl Default for HybridParams {
+    fn default() -> Self {
+        "2 Mandelbrot, Burning Ship".parse().unwrap()
+    }
+}
+
+impl FromStr for HybridParams {
+    type Err = ParseHybridError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut steps = Vec::new();
+        for step in s.split(',') {
+            let step = step.trim();
+            let digits = step
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(step.len());
+            let count = match &step[..digits] {
+                "" => 1,
+                count => count.parse::<usize>().map_err(|_| ParseHybridError)?,
+            };
+            // Not `steps.len() + count`, which overflows for huge counts
+            if count == 0 || count > MAX_HYBRID_STEPS - steps.len() {
+                return Err(ParseHybridError);
+            }
+
+            let name = step[digits..]
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ");
+            let formula = Formula::ALL
+                .into_iter()
+                .filter(|&formula| formula.has_deep_zoom() && formula != Formula::Hybrid)
+                .find(|formula| formula.to_string().eq_ignore_ascii_case(&name))
+                .ok_or(ParseHybridError)?;
+            steps.extend(std::iter::repeat_n(formula, count));
+        }
+
+        let mut params = Self {
+            formulas: [[0; 4]; MAX_HYBRID_STEPS / 4],
+            length: steps.len() as i32,
+            _padding: [0; 3],
+        };
+        for (index, &formula) in steps.iter().enumerate() {
+            params.formulas[index / 4][index % 4] = formula as i32;
+        }
+
+        Ok(params)
+    }
+}
+
+impl HybridParams {
+    /// Formula of the step taken at `iteration`, like `step_formula` in the
+    /// shader.
+    pub fn step_formula(&self, iteration: usize) -> Formula {
+        let step = iteration % self.length as usize;
+
+        Formula::ALL[self.formulas[step / 4][step % 4] as usize]
+    }
+}
+
+/// How the Buddhabrot counts are mapped to brightness, before the gamma.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Normalization {
+    /// Proportional to the count, relative to the largest one.
+    Linear = 0,
+    /// Proportional to the logarithm of the count, which brings out the
+    /// orbits crossing few pixels.
+    Logarithmic = 1,
+}
+
+impl Normalization {
+    pub const ALL: [Normalization; 2] = [Normalization::Linear, Normalization::Logarithmic];
+}
+
+impl fmt::Display for Normalization {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Normalization::Linear => "Linear",
+            Normalization::Logarithmic => "Logarithmic",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+/// Iteration limits and tone mapping of the Buddhabrot, always of `z^2 + c`.
+/// The Nebulabrot counts the red, green and blue channels with their own
+/// limits, the Buddhabrot only the red one, drawn in grey.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct BuddhabrotParams {
+    red_iterations: i32,
+    green_iterations: i32,
+    blue_iterations: i32,
+    nebulabrot: i32,
+    normalization: i32,
+    gamma: f32,
+    // Seeds the samples, so every frame adds new ones
+    frame: u32,
+}
+
+impl BuddhabrotParams {
+    pub fn new(
+        nebulabrot: bool,
+        iterations: [i32; 3],
+        normalization: Normalization,
+        gamma: f32,
+    ) -> Self {
+        let [red_iterations, green_iterations, blue_iterations] =
+            iterations.map(|iterations| iterations.clamp(0, MAX_ITERATIONS));
+
+        Self {
+            red_iterations,
+            green_iterations,
+            blue_iterations,
+            nebulabrot: nebulabrot as i32,
+            normalization: normalization as i32,
+            gamma: gamma.clamp(MIN_GAMMA, MAX_GAMMA),
+            frame: 0,
+        }
+    }
+
+    /// Whether the counts of `other` are of the same orbits, only tone mapped
+    /// differently.
+    pub fn has_same_orbits(&self, other: &Self) -> bool {
+        (
+            self.red_iterations,
+            self.green_iterations,
+            self.blue_iterations,
+            self.nebulabrot,
+        ) == (
+            other.red_iterations,
+            other.green_iterations,
+            other.blue_iterations,
+            other.nebulabrot,
+        )
+    }
+
+    pub fn set_frame(mut self, value: u32) -> Self {
+        self.frame = value;
+
+        self
+    }
+}
+
+/// Power and shading of the Mandelbulb, raised to the power in spherical
+/// coordinates. The camera orbits the origin, turned by `yaw` around the
+/// vertical axis and raised by `pitch` above the horizon, both in radians,
+/// from `distance` away; it is only set when drawing.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MandelbulbParams {
+    power: f32,
+    soft_shadows: i32,
+    ambient_occlusion: i32,
+    fog: f32,
+    yaw: f32,
+    pitch: f32,
+    distance: f32,
+}
+
+impl MandelbulbParams {
+    /// The fog is the density it thickens with over the distance travelled.
+    pub fn new(power: f32, soft_shadows: bool, ambient_occlusion: bool, fog: f32) -> Self {
+        Self {
+            power: power.clamp(MIN_BULB_POWER, MAX_BULB_POWER),
+            soft_shadows: soft_shadows as i32,
+            ambient_occlusion: ambient_occlusion as i32,
+            fog: fog.max(0.),
+            yaw: 0.,
+            pitch: 0.,
+            distance: 0.,
+        }
+    }
+
+    pub fn set_camera(mut self, (yaw, pitch, distance): (f32, f32, f32)) -> Self {
+        (self.yaw, self.pitch, self.distance) = (yaw, pitch, distance);
+
+        self
+    }
+}
+
+/// Height and contour lines of the terrain view, whose height is the smooth
+/// iteration count of each pixel. Its camera orbits the center of the view
+/// like the Mandelbulb's, and is only set when drawing.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TerrainParams {
+    height_scale: f32,
+    contours: u32,
+    yaw: f32,
+    pitch: f32,
+    distance: f32,
+}
+
+impl TerrainParams {
+    /// The height scale is the height of the points inside the set, relative
+    /// to the half height of the view. No contour lines are drawn when
+    /// `contours` is 0.
+    pub fn new(height_scale: f32, contours: u32) -> Self {
+        Self {
+            height_scale: height_scale.clamp(MIN_HEIGHT_SCALE, MAX_HEIGHT_SCALE),
+            contours: contours.min(MAX_CONTOURS),
+            yaw: 0.,
+            pitch: 0.,
+            distance: 0.,
+        }
+    }
+
+    pub fn get_height_scale(&self) -> f32 {
+        self.height_scale
+    }
+
+    pub fn set_camera(mut self, (yaw, pitch, distance): (f32, f32, f32)) -> Self {
+        (self.yaw, self.pitch, self.distance) = (yaw, pitch, distance);
+
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_coordinates() {
+        for s in ["-0.75 0.1 1", "0.25 -0.5 1e-20", "-1.5 0 1e-900"] {
+            let coordinates = s.parse::<Coordinates>().unwrap();
+            let printed = coordinates.to_string();
+            assert_eq!(printed.parse::<Coordinates>(), Ok(coordinates), "{printed}");
+        }
+
+        let coordinates = Coordinates::new((-0.75, 0.1), 2.);
+        assert_eq!(
+            coordinates.to_string().parse::<Coordinates>(),
+            Ok(coordinates)
+        );
+    }
+
+    #[test]
+    fn rejects_coordinates_out_of_bounds() {
+        assert_eq!(
+            "0 0".parse::<Coordinates>(),
+            Err(ParseCoordinatesError::Format)
+        );
+        assert_eq!(
+            "0 0 5".parse::<Coordinates>(),
+            Err(ParseCoordinatesError::ZoomOutOfRange)
+        );
+        assert_eq!(
+            "0 0 1e-1000".parse::<Coordinates>(),
+            Err(ParseCoordinatesError::ZoomOutOfRange)
+        );
+        assert_eq!(
+            "5 0 1".parse::<Coordinates>(),
+            Err(ParseCoordinatesError::CenterOutOfRange((
+                -CENTER_LIMIT,
+                CENTER_LIMIT
+            )))
+        );
+        assert_eq!(
+            Coordinates::new((0., 0.), 100.).get_zoom(),
+            FloatExp::from_f64(MAX_ZOOM)
+        );
+    }
+
+    #[test]
+    fn stops_zooming_where_the_single_kernel_runs_out() {
+        let zoom_in = |params: &FractalParams| {
+            (0..80).fold(Coordinates::new((-0.75, 0.1), 1.), |coordinates, _| {
+                coordinates.set_zoom(Zoom::In, Some((0.5, 0.5)), params.min_zoom(true))
+            })
+        };
+
+        let coordinates = zoom_in(&FractalParams::new().set_exponent(2.5));
+        assert_eq!(
+            coordinates.get_zoom(),
+            FloatExp::from_f64(DOUBLE_FLOAT_ZOOM)
+        );
+        assert_eq!(coordinates.kernel(), Kernel::Single);
+
+        let coordinates = zoom_in(&FractalParams::new().set_exponent(3.));
+        assert!(coordinates.get_zoom() < FloatExp::from_f64(1e-5));
+        assert_eq!(coordinates.kernel(), Kernel::DoubleFloat);
+
+        let coordinates = Coordinates::new((-0.75, 0.1), 1e-8)
+            .clamp_zoom(FractalParams::new().set_exponent(2.5).min_zoom(true));
+        assert_eq!(
+            coordinates.get_zoom(),
+            FloatExp::from_f64(DOUBLE_FLOAT_ZOOM)
+        );
+
+        // Devices without the perturbation kernels stop at the double-float one
+        let coordinates =
+            Coordinates::new((-0.75, 0.1), 1e-20).clamp_zoom(FractalParams::new().min_zoom(false));
+        assert_eq!(
+            coordinates.get_zoom(),
+            FloatExp::from_f64(PERTURBATION_ZOOM)
+        );
+        assert_eq!(coordinates.kernel(), Kernel::DoubleFloat);
+    }
+
+    #[test]
+    fn stops_zooming_the_single_precision_formulas() {
+        let nova = FractalParams::new().set_formula(Formula::Nova);
+        let coordinates = (0..80).fold(Coordinates::new((0., 0.), 1.), |coordinates, _| {
+            coordinates.set_zoom(Zoom::In, None, nova.min_zoom(true))
+        });
+        assert_eq!(
+            coordinates.get_zoom(),
+            FloatExp::from_f64(DOUBLE_FLOAT_ZOOM)
+        );
+
+        for formula in Formula::ALL {
+            let params = FractalParams::new().set_formula(formula);
+            assert_eq!(
+                params.min_zoom(true) == FloatExp::from_f64(DOUBLE_FLOAT_ZOOM),
+                !formula.has_deep_zoom(),
+                "{formula}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_huge_exponents_before_parsing_them() {
+        for s in ["0 0 1e-1000000000", "1e1000000000 0 1", "0 1e-1000000000 1"] {
+            assert_eq!(
+                s.parse::<Coordinates>(),
+                Err(ParseCoordinatesError::Number(ParseDecimalError)),
+                "{s}"
+            );
+        }
+    }
+
+    #[test]
+    fn parses_lyapunov_sequences() {
+        let params = "ab BA".parse::<LyapunovParams>().unwrap();
+        assert_eq!(params.length, 4);
+        assert_eq!(params.sequence[0], 0b0110);
+
+        let longest = "B"
+            .repeat(MAX_SEQUENCE_LENGTH)
+            .parse::<LyapunovParams>()
+            .unwrap();
+        assert_eq!(longest.length, MAX_SEQUENCE_LENGTH as i32);
+        assert!(longest.sequence.iter().all(|&bits| bits == u32::MAX));
+    }
+
+    #[test]
+    fn rejects_bad_lyapunov_sequences() {
+        assert_eq!("".parse::<LyapunovParams>().err(), Some(ParseSequenceError));
+        assert_eq!(
+            "ABC".parse::<LyapunovParams>().err(),
+            Some(ParseSequenceError)
+        );
+        assert_eq!(
+            "A".repeat(MAX_SEQUENCE_LENGTH + 1)
+                .parse::<LyapunovParams>()
+                .err(),
+            Some(ParseSequenceError)
+        );
+    }
+
+    #[test]
+    fn parses_hybrid_steps() {
+        let params = "2 Mandelbrot, burning  ship,1 tricorn"
+            .parse::<HybridParams>()
+            .unwrap();
+        assert_eq!(params.length, 4);
+        assert_eq!(
+            params.formulas[0],
+            [
+                Formula::Mandelbrot as i32,
+                Formula::Mandelbrot as i32,
+                Formula::BurningShip as i32,
+                Formula::Tricorn as i32
+            ]
+        );
+
+        assert_eq!(params.step_formula(6), Formula::BurningShip);
+        assert_eq!(params.step_formula(7), Formula::Tricorn);
+
+        let longest = format!("{MAX_HYBRID_STEPS} Perpendicular Celtic")
+            .parse::<HybridParams>()
+            .unwrap();
+        assert_eq!(longest.length, MAX_HYBRID_STEPS as i32);
+        assert!(
+            longest
+                .formulas
+                .iter()
+                .flatten()
+                .all(|&formula| formula == Formula::PerpendicularCeltic as i32)
+        );
+    }
+
+    #[test]
+    fn rejects_bad_hybrid_steps() {
+        for s in [
+            "",
+            "Mandelbrot,",
+            "0 Mandelbrot",
+            "Phoenix",
+            "Hybrid",
+            "2x Mandelbrot",
+            "-1 Mandelbrot",
+        ] {
+            assert_eq!(
+                s.parse::<HybridParams>().err(),
+                Some(ParseHybridError),
+                "{s:?}"
+            );
+        }
+
+        let too_many = format!("{MAX_HYBRID_STEPS} Mandelbrot, Tricorn");
+        assert!(too_many.parse::<HybridParams>().is_err());
+
+        // Counts that would overflow when added to the steps so far
+        for s in [
+            "1 Mandelbrot, 18446744073709551615 Mandelbrot",
+            "99999999999999999999999 Mandelbrot",
+        ] {
+            assert_eq!(
+                s.parse::<HybridParams>().err(),
+                Some(ParseHybridError),
+                "{s:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn keeps_the_lyapunov_center_on_its_rates() {
+        assert!(Formula::Lyapunov.parse_coordinates("3 3.5 1").is_ok());
+        assert_eq!(
+            Formula::Lyapunov.parse_coordinates("-1 3 1"),
+            Err(ParseCoordinatesError::CenterOutOfRange(LYAPUNOV_RATES))
+        );
+        assert!(Formula::Mandelbrot.parse_coordinates("-1 3 1").is_ok());
+
+        let clamped = Coordinates::new((-1., 5.), 1.).clamp_center(LYAPUNOV_RATES);
+        assert_eq!(clamped.get_complex(), (0., 4.));
+    }
+
+    #[test]
+    fn keeps_the_average_densities_positive() {
+        let params = ColorParams::new(0., 0., 0.)
+            .set_stripe_density(0.)
+            .set_triangle_density(-3.);
+        assert_eq!(params.get_stripe_density(), 1.);
+        assert_eq!(params.get_triangle_density(), 1.);
+
+        assert_eq!(params.set_triangle_density(2.5).get_triangle_density(), 2.5);
+        assert_eq!(
+            params.set_triangle_density(f32::NAN).get_triangle_density(),
+            1.
+        );
+    }
+
+    #[test]
+    fn keeps_the_custom_escape_radius_over_its_floor() {
+        let params = FractalParams::new()
+            .set_formula(Formula::Custom)
+            .set_bailout_radius(2.);
+        assert!(!params.escapes([5., 0.]));
+        assert!(params.escapes([11., 0.]));
+
+        let params = params.set_bailout_radius(SMOOTH_ESCAPE_RADIUS as f32);
+        assert!(!params.escapes([100., 0.]));
+        assert!(params.escapes([300., 0.]));
+    }
 }
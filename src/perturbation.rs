@@ -0,0 +1,537 @@
+use crate::{
+    fixed::Fixed,
+    floatexp::{self, FloatExp},
+    params::{Coordinates, Formula, FractalParams, HybridParams},
+};
+
+/// Cap on the references added to fix glitches in a single view.
+pub const MAX_REFERENCES: usize = 32;
+
+/// Iterates the fractal's formula at full precision from `start`, which is
+/// `c` itself for the Mandelbrot set like the shader kernels do. Stops after
+/// `max_iterations` steps or at the first point past the escape radius, which
+/// is kept so pixels escaping on the same step still find it. Hybrids take
+/// their steps from `hybrid`.
+pub fn reference_orbit(
+    start: (&Fixed, &Fixed),
+    constant: (&Fixed, &Fixed),
+    fractal: &FractalParams,
+    hybrid: &HybridParams,
+    max_iterations: i32,
+) -> Vec<[f32; 2]> {
+    let formula = fractal.get_formula();
+    let power = fractal.get_integer_power().unwrap_or(2);
+
+    let bits = [start.0, start.1, constant.0, constant.1]
+        .iter()
+        .map(|value| value.bits())
+        .max()
+        .unwrap_or(0);
+    let const_real = constant.0.with_bits(bits);
+    let const_imag = constant.1.with_bits(bits);
+
+    let mut z_real = start.0.with_bits(bits);
+    let mut z_imag = start.1.with_bits(bits);
+
+    let mut orbit = Vec::with_capacity(max_iterations.max(0) as usize + 1);
+    orbit.push([z_real.to_f64() as f32, z_imag.to_f64() as f32]);
+
+    for iteration in 0..max_iterations.max(0) as usize {
+        let formula = match formula {
+            Formula::Hybrid => hybrid.step_formula(iteration),
+            formula => formula,
+        };
+        let (real, imag) = formula_step(formula, power, &z_real, &z_imag);
+        z_real = real + &const_real;
+        z_imag = imag + &const_imag;
+
+        let point = [z_real.to_f64() as f32, z_imag.to_f64() as f32];
+        orbit.push(point);
+
+        if fractal.escapes(point) {
+            break;
+        }
+    }
+
+    orbit
+}
+
+/// `z` through `formula`, before `c` is added, like `formula_step` in the
+/// shader.
+fn formula_step(formula: Formula, power: u32, real: &Fixed, imag: &Fixed) -> (Fixed, Fixed) {
+    if formula == Formula::Mandelbrot {
+        let (mut power_real, mut power_imag) = (real.clone(), imag.clone());
+        for _ in 1..power {
+            (power_real, power_imag) = (
+                &power_real * real - &power_imag * imag,
+                &power_real * imag + &power_imag * real,
+            );
+        }
+
+        return (power_real, power_imag);
+    }
+
+    let square_real = real * real - imag * imag;
+    let square_real = match formula {
+        Formula::Celtic | Formula::PerpendicularCeltic => square_real.abs(),
+        _ => square_real,
+    };
+
+    let product = match formula {
+        Formula::BurningShip => (real * imag).abs(),
+        Formula::PerpendicularMandelbrot | Formula::PerpendicularCeltic => &real.abs() * imag,
+        Formula::PerpendicularBurningShip => real * &imag.abs(),
+        _ => real * imag,
+    };
+    let square_imag = product.clone() + product;
+    let square_imag = match formula {
+        Formula::Celtic => square_imag,
+        _ => -&square_imag,
+    };
+
+    (square_real, square_imag)
+}
+
+// Largest truncation error allowed for the series, relative to its first
+// term, about the precision of the f32 deltas it seeds.
+const SERIES_TOLERANCE: f64 = 1e-6;
+
+type Complex = (FloatExp, FloatExp);
+
+/// Series approximation of the deltas after `skip` iterations,
+/// dz = a d + b d^2 + c d^3 for a pixel at `d` from the reference in the
+/// screen-relative units of `Viewport::relative`. The zoom is folded into the
+/// coefficients, which are kept in extended range since they shrink with it.
+#[derive(Debug, Copy, Clone)]
+struct Series {
+    skip: i32,
+    coefficients: [Complex; 3],
+}
+
+impl Series {
+    /// Advances the coefficients along `orbit` while the cubic term stays
+    /// negligible for every pixel within `radius` of the reference. For Julia
+    /// sets the pixels share `c`, so only the starting point is offset. The
+    /// formulas using absolute values aren't analytic and skip nothing, nor
+    /// does any formula with `whole_orbit`.
+    fn new(
+        orbit: &[[f32; 2]],
+        fractal: &FractalParams,
+        zoom: FloatExp,
+        radius: f64,
+        whole_orbit: bool,
+    ) -> Self {
+        let exponent = fractal.get_integer_power().unwrap_or(2);
+        let delta_c = if fractal.is_julia() {
+            FloatExp::ZERO
+        } else {
+            zoom
+        };
+        let steps = match fractal.get_formula() {
+            Formula::Mandelbrot if !whole_orbit => orbit.len().saturating_sub(2),
+            _ => 0,
+        };
+
+        let mut a = (zoom, FloatExp::ZERO);
+        let mut b = (FloatExp::ZERO, FloatExp::ZERO);
+        let mut c = (FloatExp::ZERO, FloatExp::ZERO);
+        let mut skip = 0;
+
+        // Compared squared, |c| r^3 > tolerance |a| r
+        let error_scale = FloatExp::from_f64(radius.powi(4) / SERIES_TOLERANCE.powi(2));
+
+        // The shader still needs the orbit entry after the last skipped one
+        for z in orbit.iter().take(steps) {
+            let z = (
+                FloatExp::from_f64(z[0] as f64),
+                FloatExp::from_f64(z[1] as f64),
+            );
+
+            // Binomial expansion of (z + dz)^n - z^n up to dz^3
+            let first = scale(power(z, exponent - 1), exponent as f64);
+            let second = scale(power(z, exponent.saturating_sub(2)), binomial(exponent, 2));
+            let third = scale(power(z, exponent.saturating_sub(3)), binomial(exponent, 3));
+
+            let next_a = add(mul(first, a), (delta_c, FloatExp::ZERO));
+            let next_b = add(mul(first, b), mul(second, mul(a, a)));
+            let next_c = add(
+                add(mul(first, c), mul(second, scale(mul(a, b), 2.))),
+                mul(third, mul(a, mul(a, a))),
+            );
+
+            if norm_squared(next_c) * error_scale > norm_squared(next_a) {
+                break;
+            }
+
+            (a, b, c) = (next_a, next_b, next_c);
+            skip += 1;
+        }
+
+        Self {
+            skip,
+            coefficients: [a, b, c],
+        }
+    }
+}
+
+fn add(a: Complex, b: Complex) -> Complex {
+    (a.0 + b.0, a.1 + b.1)
+}
+
+fn mul(a: Complex, b: Complex) -> Complex {
+    (a.0 * b.0 - a.1 * b.1, a.0 * b.1 + a.1 * b.0)
+}
+
+fn scale(a: Complex, factor: f64) -> Complex {
+    (a.0.mul_f64(factor), a.1.mul_f64(factor))
+}
+
+fn norm_squared(a: Complex) -> FloatExp {
+    a.0 * a.0 + a.1 * a.1
+}
+
+fn power(a: Complex, exponent: u32) -> Complex {
+    (0..exponent).fold((FloatExp::from_f64(1.), FloatExp::ZERO), |power, _| {
+        mul(power, a)
+    })
+}
+
+fn binomial(n: u32, k: u32) -> f64 {
+    (0..k).fold(1., |value, i| value * (n - i) as f64 / (i + 1) as f64)
+}
+
+/// A reference point with its orbit, placed at `offset` from the view center
+/// in the screen-relative units of `Viewport::relative`.
+pub struct Reference {
+    offset: (f32, f32),
+    orbit: Vec<[f32; 2]>,
+    series: Series,
+}
+
+impl Reference {
+    /// `radius` is the distance from the reference to the farthest pixel it
+    /// may render, in the same units as `offset`. The fractal needs an
+    /// integer power. `whole_orbit` turns the series approximation off, for
+    /// colourings that need every point of the orbits.
+    pub fn new(
+        coordinates: &Coordinates,
+        fractal: &FractalParams,
+        hybrid: &HybridParams,
+        offset: (f32, f32),
+        radius: f32,
+        max_iterations: i32,
+        whole_orbit: bool,
+    ) -> Self {
+        let julia = fractal.get_julia();
+        let zoom = coordinates.get_zoom();
+        let (real, imag) = coordinates.get_center();
+
+        let start = (
+            real.add_float_exp(zoom.mul_f64(offset.0 as f64)),
+            imag.add_float_exp(zoom.mul_f64(offset.1 as f64)),
+        );
+        let constant = match julia {
+            Some((real, imag)) => (
+                Fixed::from_f64(real, start.0.bits()),
+                Fixed::from_f64(imag, start.1.bits()),
+            ),
+            None => start.clone(),
+        };
+
+        let orbit = reference_orbit(
+            (&start.0, &start.1),
+            (&constant.0, &constant.1),
+            fractal,
+            hybrid,
+            max_iterations,
+        );
+        let series = Series::new(&orbit, fractal, zoom, radius as f64, whole_orbit);
+
+        Self {
+            offset,
+            orbit,
+            series,
+        }
+    }
+
+    pub fn get_orbit(&self) -> &[[f32; 2]] {
+        &self.orbit
+    }
+
+    /// Iterations every pixel skips thanks to the series approximation.
+    pub fn get_skipped_iterations(&self) -> i32 {
+        self.series.skip
+    }
+
+    /// `glitch_pass` limits the render to the pixels the previous references
+    /// left glitched.
+    pub fn to_uniform(&self, glitch_pass: bool) -> ReferenceUniform {
+        let [series_a, series_b, series_c] = self.series.coefficients.map(ComplexExp::new);
+
+        ReferenceUniform {
+            real: self.offset.0,
+            imag: self.offset.1,
+            orbit_length: self.orbit.len() as i32,
+            glitch_pass: glitch_pass as i32,
+            skip: self.series.skip,
+            _padding: [0; 3],
+            series_a,
+            series_b,
+            series_c,
+        }
+    }
+}
+
+/// Complex extended-range value sharing one exponent, `mantissa * 2^exponent`,
+/// as the shader stores it.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ComplexExp {
+    mantissa: [f32; 2],
+    exponent: i32,
+    _padding: i32,
+}
+
+impl ComplexExp {
+    fn new(value: Complex) -> Self {
+        let (real, real_exponent) = value.0.to_parts();
+        let (imag, imag_exponent) = value.1.to_parts();
+
+        // Zero parts don't have a meaningful exponent to align on
+        let exponent = match (real == 0., imag == 0.) {
+            (true, true) => 0,
+            (true, false) => imag_exponent,
+            (false, true) => real_exponent,
+            (false, false) => real_exponent.max(imag_exponent),
+        };
+
+        Self {
+            mantissa: [
+                floatexp::ldexp(real, real_exponent - exponent) as f32,
+                floatexp::ldexp(imag, imag_exponent - exponent) as f32,
+            ],
+            exponent,
+            _padding: 0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ReferenceUniform {
+    real: f32,
+    imag: f32,
+    orbit_length: i32,
+    glitch_pass: i32,
+    skip: i32,
+    // Aligns the coefficients to 16 bytes like WGSL expects in uniforms
+    _padding: [i32; 3],
+    series_a: ComplexExp,
+    series_b: ComplexExp,
+    series_c: ComplexExp,
+}
+
+/// Groups the pixels flagged in `mask` into 4-connected blobs and returns one
+/// pixel per blob, the one nearest its centroid, largest blob first.
+pub fn find_glitches(mask: &[u32], width: usize, height: usize) -> Vec<(usize, usize)> {
+    let mut visited = vec![false; mask.len()];
+    let mut blobs = Vec::new();
+
+    for start in 0..mask.len().min(width * height) {
+        if mask[start] == 0 || visited[start] {
+            continue;
+        }
+
+        let mut blob = Vec::new();
+        let mut queue = vec![start];
+        visited[start] = true;
+
+        while let Some(pixel) = queue.pop() {
+            blob.push(pixel);
+
+            let (x, y) = (pixel % width, pixel / width);
+            let neighbours = [
+                (x > 0).then(|| pixel - 1),
+                (x + 1 < width).then(|| pixel + 1),
+                (y > 0).then(|| pixel - width),
+                (y + 1 < height).then(|| pixel + width),
+            ];
+            for neighbour in neighbours.into_iter().flatten() {
+                if mask[neighbour] != 0 && !visited[neighbour] {
+                    visited[neighbour] = true;
+                    queue.push(neighbour);
+                }
+            }
+        }
+
+        blobs.push(blob);
+    }
+
+    blobs.sort_by_key(|blob| std::cmp::Reverse(blob.len()));
+
+    blobs
+        .iter()
+        .map(|blob| {
+            let count = blob.len() as f64;
+            let center_x = blob.iter().map(|p| (p % width) as f64).sum::<f64>() / count;
+            let center_y = blob.iter().map(|p| (p / width) as f64).sum::<f64>() / count;
+
+            let pixel = blob
+                .iter()
+                .copied()
+                .min_by(|a, b| {
+                    let distance = |p: usize| {
+                        ((p % width) as f64 - center_x).powi(2)
+                            + ((p / width) as f64 - center_y).powi(2)
+                    };
+                    distance(*a).total_cmp(&distance(*b))
+                })
+                .unwrap_or(blob[0]);
+
+            (pixel % width, pixel / width)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn orbit_of(c: (f64, f64), fractal: &FractalParams, max_iterations: i32) -> Vec<[f32; 2]> {
+        let real = Fixed::from_f64(c.0, 64);
+        let imag = Fixed::from_f64(c.1, 64);
+
+        reference_orbit(
+            (&real, &imag),
+            (&real, &imag),
+            fractal,
+            &HybridParams::default(),
+            max_iterations,
+        )
+    }
+
+    // Points of the f64 orbit of z^2 + c from c, up to the first escaping one
+    fn f64_orbit(c: (f64, f64), fractal: &FractalParams, max_iterations: i32) -> Vec<(f64, f64)> {
+        let mut z = c;
+        let mut orbit = vec![z];
+        for _ in 0..max_iterations {
+            z = (z.0 * z.0 - z.1 * z.1 + c.0, 2. * z.0 * z.1 + c.1);
+            orbit.push(z);
+            if fractal.escapes([z.0 as f32, z.1 as f32]) {
+                break;
+            }
+        }
+
+        orbit
+    }
+
+    #[test]
+    fn escapes_with_the_f64_orbit() {
+        let fractal = FractalParams::new();
+        for c in [(0.26, 0.), (-0.75, 0.1), (-1.8, 0.01)] {
+            let orbit = orbit_of(c, &fractal, 1000);
+            let expected = f64_orbit(c, &fractal, 1000);
+            assert!(expected.len() < 1000, "{c:?} doesn't escape");
+            assert_eq!(orbit.len(), expected.len(), "{c:?}");
+            assert_eq!(orbit[0], [c.0 as f32, c.1 as f32]);
+        }
+
+        // Inside the main cardioid, the orbit runs to the last iteration
+        assert_eq!(orbit_of((-0.1, 0.1), &fractal, 100).len(), 101);
+    }
+
+    #[test]
+    fn takes_the_hybrid_steps_in_the_reference_orbit() {
+        let real = Fixed::from_f64(-1.7, 64);
+        let imag = Fixed::from_f64(-0.02, 64);
+        let orbit = |formula: Formula, hybrid: &str| {
+            reference_orbit(
+                (&real, &imag),
+                (&real, &imag),
+                &FractalParams::new().set_formula(formula),
+                &hybrid.parse().unwrap(),
+                50,
+            )
+        };
+
+        assert_eq!(
+            orbit(Formula::Hybrid, "Burning Ship"),
+            orbit(Formula::BurningShip, "Mandelbrot")
+        );
+
+        let hybrid = orbit(Formula::Hybrid, "Mandelbrot, Burning Ship");
+        let mandelbrot = orbit(Formula::Mandelbrot, "Mandelbrot");
+        assert_eq!(hybrid[..2], mandelbrot[..2]);
+        assert_ne!(hybrid[..3], mandelbrot[..3]);
+    }
+
+    #[test]
+    fn finds_the_glitched_blobs_largest_first() {
+        let (width, height) = (6, 5);
+        let mut mask = vec![0; width * height];
+        for y in 0..3 {
+            for x in 1..4 {
+                mask[y * width + x] = 7;
+            }
+        }
+        // Only touching diagonally, two blobs of their own
+        mask[3 * width + 4] = 1;
+        mask[4 * width + 5] = 12;
+
+        assert_eq!(
+            find_glitches(&mask, width, height),
+            vec![(2, 1), (4, 3), (5, 4)]
+        );
+        assert_eq!(
+            find_glitches(&vec![0; width * height], width, height),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn keeps_the_series_within_its_error_bound() {
+        let fractal = FractalParams::new();
+        let center = (-0.75, 0.1);
+        let zoom = 1e-4;
+        let radius = 2f64.hypot(1.);
+
+        let orbit = orbit_of(center, &fractal, 1000);
+        let series = Series::new(&orbit, &fractal, FloatExp::from_f64(zoom), radius, false);
+        assert!(series.skip > 0);
+        assert!((series.skip as usize) < orbit.len() - 1);
+
+        let reference = f64_orbit(center, &fractal, series.skip);
+        let [a, b, c] = series
+            .coefficients
+            .map(|(real, imag)| (real.to_f64(), imag.to_f64()));
+        let complex_mul =
+            |a: (f64, f64), b: (f64, f64)| (a.0 * b.0 - a.1 * b.1, a.0 * b.1 + a.1 * b.0);
+
+        // Pixels at the edge of the view, where the truncation is the worst
+        for offset in [(2., 1.), (-2., 1.), (0., -1.)] {
+            let pixel = (center.0 + offset.0 * zoom, center.1 + offset.1 * zoom);
+            let exact = f64_orbit(pixel, &fractal, series.skip);
+            let skip = series.skip as usize;
+            let delta = (
+                exact[skip].0 - reference[skip].0,
+                exact[skip].1 - reference[skip].1,
+            );
+
+            let square = complex_mul(offset, offset);
+            let cube = complex_mul(square, offset);
+            let (first, second, third) = (
+                complex_mul(a, offset),
+                complex_mul(b, square),
+                complex_mul(c, cube),
+            );
+            let estimate = (first.0 + second.0 + third.0, first.1 + second.1 + third.1);
+
+            // The reference orbit is rounded to f32 before the series is built
+            let error = (estimate.0 - delta.0).hypot(estimate.1 - delta.1);
+            assert!(
+                error < 1e-3 * delta.0.hypot(delta.1),
+                "{offset:?}: {estimate:?} != {delta:?}"
+            );
+        }
+    }
+}
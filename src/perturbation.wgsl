@@ -0,0 +1,326 @@
+// Perturbation kernels, appended to shader.wgsl on devices whose fragment
+// shaders can write storage buffers. perturbation_fallback.wgsl stands in
+// for them elsewhere, like on WebGL2.
+
+struct Reference {
+    real: f32,
+    imag: f32,
+    orbit_length: i32,
+    glitch_pass: i32,
+    skip: i32,
+    // Structs in uniforms start on 16 bytes
+    @align(16) series_a: ComplexExp,
+    series_b: ComplexExp,
+    series_c: ComplexExp,
+};
+
+// Orbit of the reference point, computed on the CPU at full precision.
+// Starts at z = c, one entry per iteration.
+@group(1) @binding(0)
+var<storage, read> reference_orbit: array<vec2<f32>>;
+
+// Reference position relative to the view center, in the units of
+// transform_position before zooming.
+@group(1) @binding(1)
+var<uniform> reference: Reference;
+
+// One entry per pixel, zero once a reference renders it, else one more than
+// the furthest iteration a reference took it to.
+@group(1) @binding(2)
+var<storage, read_write> glitch_mask: array<u32>;
+
+const GLITCHED: i32 = -1;
+
+// Pauldelbrot criterion, |z|^2 < GLITCH_TOLERANCE * |dz|^2: the pixel's orbit
+// came so close to zero that the f32 delta lost all its precision.
+const GLITCH_TOLERANCE: f32 = 1e-6;
+
+// Escape of the pixel at in from the perturbation kernel picked, marking the
+// glitched pixels. The passes of the later references skip the others.
+fn get_escape_perturbation(in: vec2<f32>) -> Escape {
+    let pixel = glitch_index(in);
+    if reference.glitch_pass > 0 && glitch_mask[pixel] == 0u {
+        discard;
+    }
+
+    if coordinates.kernel == KERNEL_PERTURBATION_FLOAT_EXP {
+        return record_glitch(pixel, get_iterations_perturbation_float_exp(in));
+    }
+
+    return record_glitch(pixel, get_iterations_perturbation(in));
+}
+
+// (Z + dz)^n - Z^n = dz * sum((Z + dz)^k Z^(n - 1 - k), k < n) for integer n,
+// returns the sum, built Horner style. For n = 2 it is just 2 Z + dz.
+fn perturbation_factor(reference_z: vec2<f32>, delta_z: vec2<f32>) -> vec2<f32> {
+    let z = reference_z + delta_z;
+
+    var sum = vec2(1.0, 0.0);
+    var power = reference_z;
+    for (var k = 1; k < i32(fractal_params.exponent); k++) {
+        sum = complex_mul(sum, z) + power;
+        power = complex_mul(power, reference_z);
+    }
+
+    return sum;
+}
+
+// Perturbation: iterates only the pixel's offset dz from the reference
+// orbit Z, using (Z + dz)^n + (C + dc) - (Z^n + C) = dz * factor + dc, or
+// formula_delta for the other formulas.
+// The first reference.skip iterations are replaced by a cubic series in the
+// pixel's offset from the reference, shared by the whole view.
+// Returns a glitched_escape when the reference can't be trusted for this
+// pixel.
+fn get_iterations_perturbation(in: vec2<f32>) -> Escape {
+    let x = (in.x - viewport.half_viewport_x) / viewport.half_viewport_x * viewport.ratio;
+    let y = (in.y - viewport.half_viewport_y) / viewport.half_viewport_y * -1.0;
+
+    let offset = vec2(x, y) - vec2(reference.real, reference.imag);
+    let offset_squared = complex_mul(offset, offset);
+
+    // Julia pixels all share the reference's c
+    let delta_c = select(offset * coordinates.zoom, vec2(0.0), fractal_params.julia > 0);
+    var delta_z = complex_mul(cx_to_vec2(reference.series_a), offset)
+        + complex_mul(cx_to_vec2(reference.series_b), offset_squared)
+        + complex_mul(cx_to_vec2(reference.series_c), complex_mul(offset_squared, offset));
+    var derivative = cx_to_vec2(reference.series_a)
+        + 2.0 * complex_mul(cx_to_vec2(reference.series_b), offset)
+        + 3.0 * complex_mul(cx_to_vec2(reference.series_c), offset_squared);
+    let derivative_c = select(vec2(coordinates.zoom, 0.0), vec2(0.0), fractal_params.julia > 0);
+
+    // The reference orbit starts at its c
+    let interior = interior_test(reference_orbit[0] + delta_c, INTERIOR_MARGIN);
+    if interior != INTERIOR_NONE {
+        return interior_escape(interior, NO_TRAP, OrbitAverage());
+    }
+
+    let track_derivative = tracks_derivative();
+    var trap = NO_TRAP;
+    let track_trap = color_params.coloring == COLORING_ORBIT_TRAP;
+    var average = OrbitAverage();
+    let track_average = tracks_average();
+
+    var iterations = reference.skip;
+    var dist = 0.0;
+    var z = vec2(0.0);
+
+    var saved_reference = reference_orbit[iterations];
+    var saved_delta = cx_normalize(delta_z, 0);
+    var saved_at = iterations + 1;
+
+    while iterations < max_iterations.value {
+        // The reference escaped before this pixel did
+        if iterations + 1 >= reference.orbit_length {
+            return glitched_escape(iterations, trap, average);
+        }
+
+        let reference_z = reference_orbit[iterations];
+        let previous_z = reference_z + delta_z;
+        if track_derivative {
+            derivative = complex_mul(formula_derivative(previous_z), derivative) + derivative_c;
+        }
+
+        // The reference orbit took the same hybrid steps
+        let formula = step_formula(iterations);
+        if formula == FORMULA_MANDELBROT {
+            delta_z = complex_mul(delta_z, perturbation_factor(reference_z, delta_z)) + delta_c;
+        } else {
+            delta_z = formula_delta(formula, reference_z, delta_z, 0) + delta_c;
+        }
+
+        z = reference_orbit[iterations + 1] + delta_z;
+        dist = dot(z, z);
+        if track_trap {
+            trap = min(trap, trap_distance(z));
+        }
+        if track_average {
+            // The pixel's c is only known as an offset, but it is what the
+            // step added
+            average = add_average_term(average, z, z - formula_step(formula, previous_z));
+        }
+
+        if escapes(z, dist) {
+            break;
+        }
+
+        if dist < GLITCH_TOLERANCE * dot(delta_z, delta_z) {
+            return glitched_escape(iterations, trap, average);
+        }
+
+        let delta = cx_normalize(delta_z, 0);
+        if cycles(reference_orbit[iterations + 1], delta, saved_reference, saved_delta) {
+            return interior_escape(INTERIOR_PERIODIC, trap, average);
+        }
+
+        iterations += 1;
+
+        if iterations == saved_at {
+            saved_reference = reference_orbit[iterations];
+            saved_delta = delta;
+            saved_at *= 2;
+        }
+    }
+
+    return orbit_escape(iterations, z, dist, cx_normalize(derivative, 0), trap, average);
+}
+
+// Same as get_iterations_perturbation with the deltas in extended range, for
+// zooms where they would underflow f32.
+fn get_iterations_perturbation_float_exp(in: vec2<f32>) -> Escape {
+    let x = (in.x - viewport.half_viewport_x) / viewport.half_viewport_x * viewport.ratio;
+    let y = (in.y - viewport.half_viewport_y) / viewport.half_viewport_y * -1.0;
+
+    let offset = vec2(x, y) - vec2(reference.real, reference.imag);
+    let offset_squared = complex_mul(offset, offset);
+
+    let delta_c = cx_normalize(
+        select(offset * coordinates.zoom_mantissa, vec2(0.0), fractal_params.julia > 0),
+        coordinates.zoom_exponent,
+    );
+    var delta_z = cx_add(
+        cx_add(
+            cx_mul_vec2(reference.series_a, offset),
+            cx_mul_vec2(reference.series_b, offset_squared),
+        ),
+        cx_mul_vec2(reference.series_c, complex_mul(offset_squared, offset)),
+    );
+    var derivative = cx_add(
+        cx_add(reference.series_a, cx_mul_vec2(reference.series_b, 2.0 * offset)),
+        cx_mul_vec2(reference.series_c, 3.0 * offset_squared),
+    );
+    let derivative_c = cx_normalize(
+        vec2(select(coordinates.zoom_mantissa, 0.0, fractal_params.julia > 0), 0.0),
+        coordinates.zoom_exponent,
+    );
+
+    let interior = interior_test(reference_orbit[0] + cx_to_vec2(delta_c), INTERIOR_MARGIN);
+    if interior != INTERIOR_NONE {
+        return interior_escape(interior, NO_TRAP, OrbitAverage());
+    }
+
+    let track_derivative = tracks_derivative();
+    var trap = NO_TRAP;
+    let track_trap = color_params.coloring == COLORING_ORBIT_TRAP;
+    var average = OrbitAverage();
+    let track_average = tracks_average();
+
+    var iterations = reference.skip;
+    var dist = 0.0;
+    var z = vec2(0.0);
+
+    var saved_reference = reference_orbit[iterations];
+    var saved_delta = delta_z;
+    var saved_at = iterations + 1;
+
+    while iterations < max_iterations.value {
+        // The reference escaped before this pixel did
+        if iterations + 1 >= reference.orbit_length {
+            return glitched_escape(iterations, trap, average);
+        }
+
+        let reference_z = reference_orbit[iterations];
+        let previous_z = reference_z + cx_to_vec2(delta_z);
+        if track_derivative {
+            derivative = cx_add(cx_mul_vec2(derivative, formula_derivative(previous_z)), derivative_c);
+        }
+
+        let formula = step_formula(iterations);
+        if formula == FORMULA_MANDELBROT {
+            let factor = perturbation_factor(reference_z, cx_to_vec2(delta_z));
+            delta_z = cx_add(cx_mul_vec2(delta_z, factor), delta_c);
+        } else {
+            let delta = formula_delta(formula, reference_z, delta_z.mantissa, delta_z.exponent);
+            delta_z = cx_add(cx_normalize(delta, delta_z.exponent), delta_c);
+        }
+
+        // Once dz underflows here it is far too small to cause a glitch
+        let delta = cx_to_vec2(delta_z);
+        z = reference_orbit[iterations + 1] + delta;
+        dist = dot(z, z);
+        if track_trap {
+            trap = min(trap, trap_distance(z));
+        }
+        if track_average {
+            // The pixel's c is only known as an offset, but it is what the
+            // step added
+            average = add_average_term(average, z, z - formula_step(formula, previous_z));
+        }
+
+        if escapes(z, dist) {
+            break;
+        }
+
+        if dist < GLITCH_TOLERANCE * dot(delta, delta) {
+            return glitched_escape(iterations, trap, average);
+        }
+
+        if cycles(reference_orbit[iterations + 1], delta_z, saved_reference, saved_delta) {
+            return interior_escape(INTERIOR_PERIODIC, trap, average);
+        }
+
+        iterations += 1;
+
+        if iterations == saved_at {
+            saved_reference = reference_orbit[iterations];
+            saved_delta = delta_z;
+            saved_at *= 2;
+        }
+    }
+
+    return orbit_escape(iterations, z, dist, derivative, trap, average);
+}
+
+// Brent's cycle detection of get_iterations on the pixel's orbit, Z + dz,
+// against the point saved. Z being rounded to f32 the reference has to come
+// back exactly, the deltas within the tolerance relative to the zoom.
+fn cycles(
+    reference_z: vec2<f32>,
+    delta_z: ComplexExp,
+    saved_reference: vec2<f32>,
+    saved_delta: ComplexExp,
+) -> bool {
+    if any(reference_z != saved_reference) {
+        return false;
+    }
+
+    let cycle = cx_add(delta_z, ComplexExp(-saved_delta.mantissa, saved_delta.exponent));
+    let relative = cx_scaled(cycle, -coordinates.zoom_exponent) / coordinates.zoom_mantissa;
+
+    return dot(relative, relative) < PERIODICITY_TOLERANCE;
+}
+
+fn glitch_index(in: vec2<f32>) -> u32 {
+    let width = u32(viewport.half_viewport_x * 2.0);
+
+    return u32(in.y) * width + u32(in.x);
+}
+
+// Escape of a pixel the reference can't be trusted for past `iterations`,
+// drawn as escaping there in case no reference takes it further. Its count
+// is stored as GLITCHED - iterations to tell it from a real escape.
+fn glitched_escape(iterations: i32, trap: f32, average: OrbitAverage) -> Escape {
+    let bailout = fractal_params.escape_radius * fractal_params.escape_radius;
+
+    return Escape(GLITCHED - iterations, bailout, NO_DISTANCE, INTERIOR_NONE, trap, average, vec2(0.0));
+}
+
+// Sets the pixel's entry of the glitch mask to one more than the iteration
+// its escape glitched at, or clears it. A pixel glitching again in a later
+// pass keeps the result of the reference that took it furthest.
+fn record_glitch(pixel: u32, escape: Escape) -> Escape {
+    if escape.iterations > GLITCHED {
+        glitch_mask[pixel] = 0u;
+        return escape;
+    }
+
+    var fallback = escape;
+    fallback.iterations = GLITCHED - escape.iterations;
+    let reached = u32(fallback.iterations) + 1u;
+    if reference.glitch_pass > 0 && reached <= glitch_mask[pixel] {
+        discard;
+    }
+    glitch_mask[pixel] = reached;
+
+    return fallback;
+}
@@ -0,0 +1,7 @@
+// Stands in for perturbation.wgsl on devices whose fragment shaders can't
+// write storage buffers, like WebGL2. The scene never picks the perturbation
+// kernels there, so this only has to exist.
+
+fn get_escape_perturbation(in: vec2<f32>) -> Escape {
+    return Escape(0, 0.0, NO_DISTANCE, INTERIOR_NONE, NO_TRAP, OrbitAverage(), vec2(0.0));
+}
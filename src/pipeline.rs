@@ -0,0 +1,82 @@
+use iced_wgpu::wgpu;
+
+use crate::uniform::Bindings;
+
+// Structs and functions every shader uses, put before their sources
+const COMMON: &str = include_str!("common.wgsl");
+
+/// Shader module of `source` after the common prelude.
+pub fn shader_module(device: &wgpu::Device, label: &str, source: &str) -> wgpu::ShaderModule {
+    device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(label),
+        source: wgpu::ShaderSource::Wgsl(format!("{COMMON}{source}").into()),
+    })
+}
+
+/// Layout of the pipelines using `bindings`, as bind groups 0 and up.
+pub fn pipeline_layout(device: &wgpu::Device, bindings: &[&Bindings]) -> wgpu::PipelineLayout {
+    let bind_group_layouts: Vec<&wgpu::BindGroupLayout> = bindings
+        .iter()
+        .map(|bindings| bindings.get_bind_group_layout())
+        .collect();
+
+    device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: None,
+        push_constant_ranges: &[],
+        bind_group_layouts: &bind_group_layouts,
+    })
+}
+
+/// Target the fragments replace the pixels of.
+pub fn replace_target(format: wgpu::TextureFormat) -> wgpu::ColorTargetState {
+    wgpu::ColorTargetState {
+        format,
+        blend: Some(wgpu::BlendState {
+            color: wgpu::BlendComponent::REPLACE,
+            alpha: wgpu::BlendComponent::REPLACE,
+        }),
+        write_mask: wgpu::ColorWrites::ALL,
+    }
+}
+
+/// Pipeline drawing the triangles of the `(vertex, fragment)` entry points
+/// into `target`, depth tested when `depth_stencil` is given.
+pub fn render_pipeline(
+    device: &wgpu::Device,
+    label: &str,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    (vertex, fragment): (&str, &str),
+    target: wgpu::ColorTargetState,
+    depth_stencil: Option<wgpu::DepthStencilState>,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some(vertex),
+            buffers: &[],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: Some(fragment),
+            targets: &[Some(target)],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            front_face: wgpu::FrontFace::Ccw,
+            ..Default::default()
+        },
+        depth_stencil,
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+        cache: None,
+    })
+}
@@ -1,73 +1,229 @@
+use std::{future::Future, pin::Pin};
+
+use futures::FutureExt;
 use iced_wgpu::{
     core::Color,
-    wgpu::{self, BindGroupLayout, ShaderStages},
+    wgpu::{self, ShaderStages},
 };
 
 use crate::{
-    params::{ColorParams, Coordinates, Iterations, Viewport, Zoom},
-    uniform::Uniform,
+    buddhabrot::Buddhabrot,
+    expression::Expression,
+    floatexp::FloatExp,
+    mandelbulb::Mandelbulb,
+    params::{
+        BailoutNorm, BuddhabrotParams, ColorParams, Coloring, Coordinates, CoordinatesUniform,
+        Formula, FractalParams, HybridParams, Iterations, Kernel, LightParams, LyapunovParams,
+        MAX_ITERATIONS, MandelbulbParams, NewtonParams, TerrainParams, TrapParams, Viewport, Zoom,
+    },
+    perturbation::{self, MAX_REFERENCES, Reference, ReferenceUniform},
+    pipeline,
+    terrain::{self, Terrain},
+    uniform::{Bindings, ShaderOutput, Storage, Uniform},
 };
 
+// Comments around the expression `custom_step` returns in the shader, which
+// a compiled formula replaces
+const CUSTOM_STEP_START: &str = "/* custom_step { */";
+const CUSTOM_STEP_END: &str = "/* } custom_step */";
+
+/// What the device can do past WebGL2, which has neither storage buffers nor
+/// compute shaders. The perturbation kernels need fragment shaders writing
+/// storage buffers, and the Buddhabrot a compute shader on top.
+#[derive(Debug, Copy, Clone)]
+pub struct Capabilities {
+    pub perturbation: bool,
+    pub buddhabrot: bool,
+}
+
+impl Capabilities {
+    pub fn new(adapter: &wgpu::Adapter, device: &wgpu::Device) -> Self {
+        let flags = adapter.get_downlevel_capabilities().flags;
+        let storage = device.limits().max_storage_buffers_per_shader_stage >= 2
+            && flags.contains(wgpu::DownlevelFlags::FRAGMENT_WRITABLE_STORAGE);
+
+        Self {
+            perturbation: storage,
+            buddhabrot: storage && flags.contains(wgpu::DownlevelFlags::COMPUTE_SHADERS),
+        }
+    }
+}
+
+/// Pipelines of a custom formula, swapped in once the device has validated
+/// them. The check only completes asynchronously on the web.
+struct PendingFormula {
+    pipelines: (wgpu::RenderPipeline, wgpu::RenderPipeline),
+    error: Pin<Box<dyn Future<Output = Option<wgpu::Error>>>>,
+}
+
+/// Buffers of the perturbation kernels, bound as the second group: the orbit
+/// of the reference drawn, its position and series, and the glitch mask.
+struct ReferenceBindings {
+    orbit: Storage<[f32; 2]>,
+    uniform: Uniform<ReferenceUniform>,
+    glitch_mask: ShaderOutput<u32>,
+    bindings: Bindings,
+}
+
+impl ReferenceBindings {
+    fn new(device: &wgpu::Device, viewport: &Viewport) -> Self {
+        let orbit = Storage::new("ReferenceOrbit", MAX_ITERATIONS as usize + 1, device);
+        let uniform = Uniform::new("Reference", ReferenceUniform::default(), device);
+        let (width, height) = viewport.get_size();
+        let glitch_mask = ShaderOutput::new("GlitchMask", width * height, device);
+        let bindings = Bindings::new(
+            "Reference",
+            &[&orbit, &uniform, &glitch_mask],
+            ShaderStages::FRAGMENT,
+            device,
+        );
+
+        Self {
+            orbit,
+            uniform,
+            glitch_mask,
+            bindings,
+        }
+    }
+
+    /// Starts a glitch mask of the new size.
+    fn resize(&mut self, device: &wgpu::Device, viewport: &Viewport) {
+        let (width, height) = viewport.get_size();
+        self.glitch_mask = ShaderOutput::new("GlitchMask", width * height, device);
+        self.bindings.rebind(
+            "Reference",
+            &[&self.orbit, &self.uniform, &self.glitch_mask],
+            device,
+        );
+    }
+}
+
 pub struct Scene {
     pipeline: wgpu::RenderPipeline,
+    height_pipeline: wgpu::RenderPipeline,
+    texture_format: wgpu::TextureFormat,
     viewport: Uniform<Viewport>,
-    coordinates: Uniform<Coordinates>,
+    coordinates: Coordinates,
+    // View of the set not shown, restored when switching back to it
+    stashed_coordinates: Coordinates,
+    coordinates_uniform: Uniform<CoordinatesUniform>,
     pub iterations: Uniform<Iterations>,
     color_params: Uniform<ColorParams>,
+    fractal_params: Uniform<FractalParams>,
+    newton_params: Uniform<NewtonParams>,
+    trap_params: Uniform<TrapParams>,
+    light_params: Uniform<LightParams>,
+    lyapunov_params: Uniform<LyapunovParams>,
+    hybrid_params: Uniform<HybridParams>,
+    params: Bindings,
+    // None when the device can't run the perturbation kernels
+    reference: Option<ReferenceBindings>,
+    references: Vec<Reference>,
+    capabilities: Capabilities,
+    reference_outdated: bool,
+    glitch_search: bool,
+    pending_formula: Option<PendingFormula>,
+    // Drawn instead of the escape-time fractal while it is set
+    buddhabrot: Option<Buddhabrot>,
+    mandelbulb: Option<Mandelbulb>,
+    // Draws the heights of the escape-time fractal in 3D while it is set
+    terrain: Option<Terrain>,
 }
 
 impl Scene {
     pub fn new(
         device: &wgpu::Device,
+        capabilities: Capabilities,
         texture_format: wgpu::TextureFormat,
         window_size: [f32; 2],
     ) -> Scene {
-        let viewport = Uniform::new(
-            "Viewport",
-            Viewport::new(window_size),
-            ShaderStages::FRAGMENT,
-            device,
-        );
-        let coordinates = Uniform::new(
+        let viewport = Uniform::new("Viewport", Viewport::new(window_size), device);
+        let coordinates = Coordinates::default();
+        let coordinates_uniform = Uniform::new(
             "Coordinates",
-            Coordinates::default(),
-            ShaderStages::FRAGMENT,
+            coordinates.to_uniform(coordinates.kernel()),
             device,
         );
-        let iterations = Uniform::new(
-            "Iterations",
-            Iterations::new(1000),
+        let iterations = Uniform::new("Iterations", Iterations::new(1000), device);
+        let color_params = Uniform::new("ColorParams", ColorParams::new(0.5, 1.0, 1.0), device);
+        let fractal_params = Uniform::new("FractalParams", FractalParams::new(), device);
+        let newton_params = Uniform::new("NewtonParams", NewtonParams::default(), device);
+        let trap_params = Uniform::new("TrapParams", TrapParams::default(), device);
+        let light_params = Uniform::new("LightParams", LightParams::default(), device);
+        let lyapunov_params = Uniform::new("LyapunovParams", LyapunovParams::default(), device);
+        let hybrid_params = Uniform::new("HybridParams", HybridParams::default(), device);
+        let params = Bindings::new(
+            "Params",
+            &[
+                &viewport,
+                &coordinates_uniform,
+                &iterations,
+                &color_params,
+                &fractal_params,
+                &newton_params,
+                &trap_params,
+                &light_params,
+                &lyapunov_params,
+                &hybrid_params,
+            ],
             ShaderStages::FRAGMENT,
             device,
         );
-        let color_params = Uniform::new(
-            "ColorParams",
-            ColorParams::new(0.5, 1.0, 1.0),
-            ShaderStages::FRAGMENT,
-            device,
-        );
-        let pipeline = build_pipeline(
-            device,
-            texture_format,
-            viewport.get_bind_group_layout(),
-            coordinates.get_bind_group_layout(),
-            iterations.get_bind_group_layout(),
-            color_params.get_bind_group_layout(),
-        );
+
+        let reference = capabilities
+            .perturbation
+            .then(|| ReferenceBindings::new(device, &viewport));
+
+        let (pipeline, height_pipeline) =
+            build_pipelines(device, texture_format, &params, reference.as_ref(), None);
 
         Scene {
             pipeline,
+            height_pipeline,
+            texture_format,
             viewport,
             coordinates,
+            stashed_coordinates: Coordinates::julia(2.),
+            coordinates_uniform,
             iterations,
             color_params,
+            fractal_params,
+            newton_params,
+            trap_params,
+            light_params,
+            lyapunov_params,
+            hybrid_params,
+            params,
+            reference,
+            references: Vec::new(),
+            capabilities,
+            reference_outdated: true,
+            glitch_search: false,
+            pending_formula: None,
+            buddhabrot: None,
+            mandelbulb: None,
+            terrain: None,
         }
     }
 
-    pub fn clear<'a>(
+    /// Starts a render pass on `target`, clearing it to `background_color`
+    /// or keeping what is already there when it is `None`.
+    pub fn begin_render_pass<'a>(
         target: &'a wgpu::TextureView,
         encoder: &'a mut wgpu::CommandEncoder,
-        background_color: Color,
+        background_color: Option<Color>,
+    ) -> wgpu::RenderPass<'a> {
+        Scene::begin_depth_render_pass(target, encoder, background_color, None)
+    }
+
+    /// Starts a render pass like [`Scene::begin_render_pass`], testing
+    /// against the `depth` texture cleared to the far plane when there is
+    /// one.
+    pub fn begin_depth_render_pass<'a>(
+        target: &'a wgpu::TextureView,
+        encoder: &'a mut wgpu::CommandEncoder,
+        background_color: Option<Color>,
+        depth: Option<&'a wgpu::TextureView>,
     ) -> wgpu::RenderPass<'a> {
         encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: None,
@@ -76,62 +232,350 @@ impl Scene {
                 depth_slice: None,
                 resolve_target: None,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear({
-                        let [r, g, b, a] = background_color.into_linear();
-
-                        wgpu::Color {
-                            r: r as f64,
-                            g: g as f64,
-                            b: b as f64,
-                            a: a as f64,
-                        }
-                    }),
+                    load: match background_color {
+                        Some(background_color) => wgpu::LoadOp::Clear({
+                            let [r, g, b, a] = background_color.into_linear();
+
+                            wgpu::Color {
+                                r: r as f64,
+                                g: g as f64,
+                                b: b as f64,
+                                a: a as f64,
+                            }
+                        }),
+                        None => wgpu::LoadOp::Load,
+                    },
                     store: wgpu::StoreOp::Store,
                 },
             })],
-            depth_stencil_attachment: None,
+            depth_stencil_attachment: depth.map(|depth| wgpu::RenderPassDepthStencilAttachment {
+                view: depth,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.),
+                    store: wgpu::StoreOp::Discard,
+                }),
+                stencil_ops: None,
+            }),
             timestamp_writes: None,
             occlusion_query_set: None,
         })
     }
 
-    pub fn resize(&mut self, window_size: [f32; 2]) {
+    pub fn resize(&mut self, device: &wgpu::Device, window_size: [f32; 2]) {
         println!("window_size: {:?}", window_size);
         self.viewport.set(Viewport::new(window_size));
+
+        if let Some(reference) = &mut self.reference {
+            reference.resize(device, &self.viewport);
+        }
+        if let Some(buddhabrot) = &mut self.buddhabrot {
+            buddhabrot.resize(device, &self.viewport, &self.coordinates_uniform);
+        }
+        if let Some(terrain) = &mut self.terrain {
+            terrain.resize(device, &self.viewport, &self.color_params, &self.iterations);
+        }
+        self.reference_outdated = true;
     }
 
     pub fn move_center(&mut self, motion: (f32, f32)) {
         println!("move center {:?}", motion);
-        let change_x = motion.0 / self.viewport.half_viewport_x * 2. * self.coordinates.get_zoom();
-        let change_y = motion.1 / self.viewport.half_viewport_y * 2. * self.coordinates.get_zoom();
+        let zoom = self.coordinates.get_zoom();
+        let change_x = zoom.mul_f64((motion.0 / self.viewport.half_viewport_x * 2.) as f64);
+        let change_y = zoom.mul_f64((motion.1 / self.viewport.half_viewport_y * 2.) as f64);
+
+        self.coordinates = self
+            .coordinates
+            .clone()
+            .translate((change_x, -change_y))
+            .clamp_center(self.fractal_params.get_formula().center_range());
+        self.reference_outdated = true;
+    }
 
-        let (real, imag) = self.coordinates.get_complex();
+    pub fn zoom(&mut self, zoom: Zoom, cursor_pos: Option<(f32, f32)>) {
+        self.coordinates = self
+            .coordinates
+            .clone()
+            .set_zoom(
+                zoom,
+                cursor_pos.map(|c| self.viewport.relative(c)),
+                self.min_zoom(),
+            )
+            .clamp_center(self.fractal_params.get_formula().center_range());
+        self.reference_outdated = true;
+    }
 
-        let new_real = real + change_x;
-        let new_imag = imag + change_y;
+    pub fn get_coordinates(&self) -> &Coordinates {
+        &self.coordinates
+    }
 
-        self.coordinates
-            .set(self.coordinates.set_complex((new_real, new_imag)));
+    /// Moves to `coordinates`, zoomed out to the deepest zoom the formula
+    /// resolves if they are past it.
+    pub fn set_coordinates(&mut self, coordinates: Coordinates) {
+        self.coordinates = coordinates.clamp_zoom(self.min_zoom());
+        self.reference_outdated = true;
     }
 
-    pub fn zoom(&mut self, zoom: Zoom, cursor_pos: Option<(f32, f32)>) {
-        self.coordinates.set(self.coordinates.set_zoom(
-            zoom,
-            cursor_pos.map(|c| {
-                let ratio = self.viewport.ratio;
+    // Deepest zoom the formula resolves with the kernels the device runs
+    fn min_zoom(&self) -> FloatExp {
+        self.fractal_params.min_zoom(self.reference.is_some())
+    }
 
-                let cur_rel_x =
-                    (c.0 - self.viewport.half_viewport_x) / self.viewport.half_viewport_x * ratio;
-                let cur_rel_y =
-                    (c.1 - self.viewport.half_viewport_y) / self.viewport.half_viewport_y * -1.;
+    // Zooms both views out to what the formula and power resolve, after they
+    // changed
+    fn clamp_zooms(&mut self) {
+        let min_zoom = self.min_zoom();
+        self.coordinates = self.coordinates.clone().clamp_zoom(min_zoom);
+        self.stashed_coordinates = self.stashed_coordinates.clone().clamp_zoom(min_zoom);
+    }
 
-                (cur_rel_x, cur_rel_y)
-            }),
-        ));
+    /// Point of the Mandelbrot set under `position`, even while a Julia set
+    /// is shown.
+    pub fn mandelbrot_point_at(&self, position: (f32, f32)) -> (f64, f64) {
+        let coordinates = if self.fractal_params.is_julia() {
+            &self.stashed_coordinates
+        } else {
+            &self.coordinates
+        };
+
+        coordinates.point_at(self.viewport.relative(position))
+    }
+
+    pub fn get_fractal_params(&self) -> &FractalParams {
+        &self.fractal_params
+    }
+
+    /// Switches between the Mandelbrot and the Julia set, each keeping its
+    /// own view.
+    pub fn set_julia(&mut self, julia: bool) {
+        if self.fractal_params.is_julia() != julia {
+            std::mem::swap(&mut self.coordinates, &mut self.stashed_coordinates);
+            self.fractal_params
+                .set(self.fractal_params.set_julia(julia));
+            self.reference_outdated = true;
+        }
+    }
+
+    pub fn set_julia_constant(&mut self, constant: (f64, f64)) {
+        self.fractal_params
+            .set(self.fractal_params.set_julia_constant(constant));
+        self.reference_outdated |= self.fractal_params.is_julia();
+    }
+
+    /// Changes the power of the Multibrot or Julia set, keeping the views
+    /// until [`Self::reset_views`] frames the new sets. Powers only the
+    /// single precision kernel handles zoom them out to its limit.
+    pub fn set_exponent(&mut self, exponent: f32) {
+        self.fractal_params
+            .set(self.fractal_params.set_exponent(exponent));
+        self.clamp_zooms();
+        self.reference_outdated = true;
+    }
+
+    pub fn set_phoenix(&mut self, phoenix: (f32, f32)) {
+        self.fractal_params
+            .set(self.fractal_params.set_phoenix(phoenix));
+    }
+
+    pub fn set_relaxation(&mut self, relaxation: f32) {
+        self.fractal_params
+            .set(self.fractal_params.set_relaxation(relaxation));
+    }
+
+    pub fn set_bailout_radius(&mut self, radius: f32) {
+        self.fractal_params
+            .set(self.fractal_params.set_bailout_radius(radius));
+        self.reference_outdated = true;
+    }
+
+    pub fn set_bailout_norm(&mut self, norm: BailoutNorm) {
+        self.fractal_params
+            .set(self.fractal_params.set_bailout_norm(norm));
+        self.reference_outdated = true;
+    }
+
+    /// Switches to another escape-time formula, resetting both views to
+    /// frame its sets.
+    pub fn set_formula(&mut self, formula: Formula) {
+        self.fractal_params
+            .set(self.fractal_params.set_formula(formula));
+        self.reset_views();
+    }
+
+    /// Moves both the Mandelbrot and Julia views to the defaults of the
+    /// formula and power.
+    pub fn reset_views(&mut self) {
+        let power = self.fractal_params.get_power();
+        let (mandelbrot, julia) = (
+            self.fractal_params.get_formula().default_coordinates(power),
+            Coordinates::julia(power),
+        );
+        (self.coordinates, self.stashed_coordinates) = if self.fractal_params.is_julia() {
+            (julia, mandelbrot)
+        } else {
+            (mandelbrot, julia)
+        };
+        self.reference_outdated = true;
+    }
+
+    /// Compiles `expression` into the shader as the custom formula, drawn
+    /// once [`Scene::poll_custom_formula`] finds the generated shader valid.
+    /// The current pipeline is kept if it isn't.
+    pub fn set_custom_formula(&mut self, device: &wgpu::Device, expression: &Expression) {
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let pipelines = build_pipelines(
+            device,
+            self.texture_format,
+            &self.params,
+            self.reference.as_ref(),
+            Some(&expression.to_wgsl()),
+        );
+
+        self.pending_formula = Some(PendingFormula {
+            pipelines,
+            error: Box::pin(device.pop_error_scope()),
+        });
+    }
+
+    /// Result of the custom formula compiled last, once the device is done
+    /// validating it, swapping its pipelines in if it is valid. Never waits.
+    pub fn poll_custom_formula(&mut self) -> Option<Result<(), wgpu::Error>> {
+        let pending = self.pending_formula.as_mut()?;
+        let error = pending.error.as_mut().now_or_never()?;
+        let pending = self.pending_formula.take()?;
+
+        match error {
+            Some(error) => Some(Err(error)),
+            None => {
+                (self.pipeline, self.height_pipeline) = pending.pipelines;
+                Some(Ok(()))
+            }
+        }
+    }
+
+    /// Roots of the polynomial drawn by the Newton formula.
+    pub fn set_newton_roots(&mut self, roots: &[(f64, f64)]) {
+        self.newton_params.set(NewtonParams::new(roots));
+    }
+
+    /// A/B sequence of the Lyapunov fractal.
+    pub fn set_lyapunov_params(&mut self, lyapunov_params: LyapunovParams) {
+        self.lyapunov_params.set(lyapunov_params);
+    }
+
+    /// Formulas the hybrid cycles through.
+    pub fn set_hybrid_params(&mut self, hybrid_params: HybridParams) {
+        self.hybrid_params.set(hybrid_params);
+        self.reference_outdated = true;
+    }
+
+    pub fn get_buddhabrot_params(&self) -> Option<BuddhabrotParams> {
+        self.buddhabrot.as_ref().map(Buddhabrot::get_params)
+    }
+
+    /// Draws the Buddhabrot with `params`, or the escape-time fractal again
+    /// when it is `None` or the device can't run its compute shader.
+    pub fn set_buddhabrot_params(
+        &mut self,
+        device: &wgpu::Device,
+        params: Option<BuddhabrotParams>,
+    ) {
+        let params = params.filter(|_| self.capabilities.buddhabrot);
+        match (&mut self.buddhabrot, params) {
+            (Some(buddhabrot), Some(params)) => buddhabrot.set_params(params),
+            (None, Some(params)) => {
+                self.buddhabrot = Some(Buddhabrot::new(
+                    device,
+                    self.texture_format,
+                    &self.viewport,
+                    &self.coordinates_uniform,
+                    params,
+                ));
+            }
+            (_, None) => self.buddhabrot = None,
+        }
+    }
+
+    pub fn get_mandelbulb_params(&self) -> Option<MandelbulbParams> {
+        self.mandelbulb.as_ref().map(Mandelbulb::get_params)
+    }
+
+    /// Draws the Mandelbulb with `params`, or the escape-time fractal again
+    /// when it is `None`.
+    pub fn set_mandelbulb_params(
+        &mut self,
+        device: &wgpu::Device,
+        params: Option<MandelbulbParams>,
+    ) {
+        match (&mut self.mandelbulb, params) {
+            (Some(mandelbulb), Some(params)) => mandelbulb.set_params(params),
+            (None, Some(params)) => {
+                self.mandelbulb = Some(Mandelbulb::new(
+                    device,
+                    self.texture_format,
+                    &self.viewport,
+                    &self.color_params,
+                    params,
+                ));
+            }
+            (_, None) => self.mandelbulb = None,
+        }
+    }
+
+    pub fn get_terrain_params(&self) -> Option<TerrainParams> {
+        self.terrain.as_ref().map(Terrain::get_params)
+    }
+
+    /// Draws the escape-time fractal as a terrain with `params`, or flat
+    /// again when it is `None`.
+    pub fn set_terrain_params(&mut self, device: &wgpu::Device, params: Option<TerrainParams>) {
+        match (&mut self.terrain, params) {
+            (Some(terrain), Some(params)) => terrain.set_params(params),
+            (None, Some(params)) => {
+                self.terrain = Some(Terrain::new(
+                    device,
+                    self.texture_format,
+                    &self.viewport,
+                    &self.color_params,
+                    &self.iterations,
+                    params,
+                ));
+            }
+            (_, None) => self.terrain = None,
+        }
+    }
+
+    /// Turns the camera of the Mandelbulb or the terrain by the motion of
+    /// the cursor.
+    pub fn orbit_camera(&mut self, motion: (f32, f32)) {
+        if let Some(mandelbulb) = &mut self.mandelbulb {
+            mandelbulb.orbit(motion);
+        }
+        if let Some(terrain) = &mut self.terrain {
+            terrain.orbit(motion);
+        }
+    }
+
+    /// Moves the camera of the Mandelbulb or the terrain closer or farther.
+    pub fn dolly_camera(&mut self, zoom: Zoom) {
+        if let Some(mandelbulb) = &mut self.mandelbulb {
+            mandelbulb.dolly(zoom);
+        }
+        if let Some(terrain) = &mut self.terrain {
+            terrain.dolly(zoom);
+        }
     }
 
     pub fn set_iterations(&mut self, iterations: i32) {
         self.iterations.set(Iterations::new(iterations));
+        self.reference_outdated = true;
+    }
+
+    /// Iterations the series approximation of the main reference let every
+    /// pixel skip in the last frame.
+    pub fn get_skipped_iterations(&self) -> i32 {
+        self.references
+            .first()
+            .map_or(0, Reference::get_skipped_iterations)
     }
 
     pub fn get_color_params(&self) -> &ColorParams {
@@ -146,74 +590,352 @@ impl Scene {
         self.color_params.set(self.color_params.set_link(hsl_link));
     }
 
-    pub fn draw<'a>(&'a mut self, render_pass: &mut wgpu::RenderPass<'a>, queue: &wgpu::Queue) {
+    pub fn set_smooth(&mut self, smooth: bool) {
+        self.color_params.set(self.color_params.set_smooth(smooth));
+    }
+
+    pub fn set_coloring(&mut self, coloring: Coloring) {
+        // The series approximation skips points some colourings need
+        let whole_orbit = self.color_params.get_coloring().needs_whole_orbit();
+        self.reference_outdated |= coloring.needs_whole_orbit() != whole_orbit;
+
+        self.color_params
+            .set(self.color_params.set_coloring(coloring));
+    }
+
+    pub fn set_interior_overlay(&mut self, interior_overlay: bool) {
+        self.color_params
+            .set(self.color_params.set_interior_overlay(interior_overlay));
+    }
+
+    pub fn set_stripe_density(&mut self, stripe_density: f32) {
+        self.color_params
+            .set(self.color_params.set_stripe_density(stripe_density));
+    }
+
+    pub fn set_triangle_density(&mut self, triangle_density: f32) {
+        self.color_params
+            .set(self.color_params.set_triangle_density(triangle_density));
+    }
+
+    pub fn get_trap_params(&self) -> &TrapParams {
+        &self.trap_params
+    }
+
+    pub fn set_trap_params(&mut self, trap_params: TrapParams) {
+        self.trap_params.set(trap_params);
+    }
+
+    pub fn get_light_params(&self) -> &LightParams {
+        &self.light_params
+    }
+
+    pub fn set_light_params(&mut self, light_params: LightParams) {
+        self.light_params.set(light_params);
+    }
+
+    /// Kernel for the current zoom, or the single precision one when the
+    /// formula or its power is only handled by that one. Devices that can't
+    /// run the perturbation kernels stop zooming at the double-float one,
+    /// see [`FractalParams::min_zoom`], and stay on it.
+    fn kernel(&self) -> Kernel {
+        if !self.fractal_params.has_deep_zoom() {
+            return Kernel::Single;
+        }
+
+        match self.coordinates.kernel() {
+            kernel if kernel.is_perturbation() && self.reference.is_none() => Kernel::DoubleFloat,
+            kernel => kernel,
+        }
+    }
+
+    /// Starts over from a single reference at the view center when the
+    /// perturbation kernel needs one.
+    fn update_references(&mut self) {
+        self.references.clear();
+        if self.kernel().is_perturbation() {
+            self.references.push(Reference::new(
+                &self.coordinates,
+                &self.fractal_params,
+                &self.hybrid_params,
+                (0., 0.),
+                self.viewport.radius_from((0., 0.)),
+                **self.iterations,
+                self.color_params.get_coloring().needs_whole_orbit(),
+            ));
+        }
+
+        if let Some(reference) = &mut self.reference {
+            reference.glitch_mask.discard();
+        }
+        self.glitch_search = !self.references.is_empty();
+        self.reference_outdated = false;
+    }
+
+    /// Whether the view keeps changing without input, and so has to be
+    /// drawn again: the Buddhabrot adds samples until it has them all, the
+    /// perturbation kernels add references until the view is glitch free,
+    /// and a custom formula is swapped in once validated.
+    pub fn needs_redraw(&self) -> bool {
+        if self.pending_formula.is_some() {
+            return true;
+        }
+        if self.mandelbulb.is_some() {
+            return false;
+        }
+
+        match &self.buddhabrot {
+            Some(buddhabrot) => !buddhabrot.is_complete(),
+            None => self.glitch_search,
+        }
+    }
+
+    /// Draws the whole view with the first reference, then only the pixels
+    /// still glitched with each following one. Until the view is glitch free
+    /// the glitch mask is copied back after the passes, and once a copy from
+    /// an earlier frame arrives a new reference is added inside every
+    /// glitched blob. With the terrain set the passes draw the heights it is
+    /// then drawn from. The Mandelbulb or the Buddhabrot, when set, is drawn
+    /// instead.
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        target: &wgpu::TextureView,
+        background_color: Color,
+    ) {
+        if let Some(mandelbulb) = &mut self.mandelbulb {
+            self.viewport.upload(queue);
+            self.color_params.upload(queue);
+
+            mandelbulb.render(device, queue, target, background_color);
+            return;
+        }
+
+        if let Some(buddhabrot) = &mut self.buddhabrot {
+            // The orbits are f32, but the center keeps its low parts
+            let view = self.coordinates.to_uniform(Kernel::DoubleFloat);
+            self.coordinates_uniform.set(view);
+            self.viewport.upload(queue);
+            self.coordinates_uniform.upload(queue);
+
+            buddhabrot.render(device, queue, target, background_color, view);
+            return;
+        }
+
+        if self.reference_outdated {
+            self.update_references();
+        }
+        self.coordinates_uniform
+            .set(self.coordinates.to_uniform(self.kernel()));
+
         self.viewport.upload(queue);
-        self.coordinates.upload(queue);
+        self.coordinates_uniform.upload(queue);
         self.iterations.upload(queue);
         self.color_params.upload(queue);
+        self.fractal_params.upload(queue);
+        self.newton_params.upload(queue);
+        self.trap_params.upload(queue);
+        self.light_params.upload(queue);
+        self.lyapunov_params.upload(queue);
+        self.hybrid_params.upload(queue);
+
+        for index in 0..self.references.len().max(1) {
+            self.draw_pass(device, queue, target, background_color, index);
+        }
+
+        if self.glitch_search {
+            self.search_glitches(device, queue, target, background_color);
+        }
+
+        if let Some(terrain) = &mut self.terrain {
+            terrain.render(device, queue, target, background_color);
+        }
+    }
+
+    /// Adds a reference inside every glitched blob of the last glitch mask
+    /// read back, and draws its pass, then copies the mask back again. Never
+    /// waits for the copy, which only shows up in a later frame.
+    fn search_glitches(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        target: &wgpu::TextureView,
+        background_color: Color,
+    ) {
+        let mask = self
+            .reference
+            .as_mut()
+            .and_then(|reference| reference.glitch_mask.try_read(device));
+
+        if let Some(mask) = mask {
+            let (width, height) = self.viewport.get_size();
+            let glitches = perturbation::find_glitches(&mask, width, height);
+
+            let room = MAX_REFERENCES.saturating_sub(self.references.len());
+            for &(x, y) in glitches.iter().take(room) {
+                let offset = self.viewport.relative((x as f32 + 0.5, y as f32 + 0.5));
+                self.references.push(Reference::new(
+                    &self.coordinates,
+                    &self.fractal_params,
+                    &self.hybrid_params,
+                    offset,
+                    self.viewport.radius_from(offset),
+                    **self.iterations,
+                    self.color_params.get_coloring().needs_whole_orbit(),
+                ));
+
+                let index = self.references.len() - 1;
+                self.draw_pass(device, queue, target, background_color, index);
+            }
+
+            self.glitch_search = !glitches.is_empty() && self.references.len() < MAX_REFERENCES;
+        }
+
+        if let Some(reference) = &mut self.reference
+            && self.glitch_search
+        {
+            reference.glitch_mask.request(device, queue);
+        }
+    }
 
-        render_pass.set_pipeline(&self.pipeline);
-        render_pass.set_bind_group(0, self.viewport.get_bind_group(), &[]);
-        render_pass.set_bind_group(1, self.coordinates.get_bind_group(), &[]);
-        render_pass.set_bind_group(2, self.iterations.get_bind_group(), &[]);
-        render_pass.set_bind_group(3, self.color_params.get_bind_group(), &[]);
-        render_pass.draw(0..6, 0..1);
+    fn draw_pass(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        target: &wgpu::TextureView,
+        background_color: Color,
+        index: usize,
+    ) {
+        if let Some(bindings) = &mut self.reference {
+            let reference_uniform = match self.references.get(index) {
+                Some(reference) => {
+                    bindings.orbit.set(reference.get_orbit());
+                    reference.to_uniform(index > 0)
+                }
+                None => ReferenceUniform::default(),
+            };
+            bindings.uniform.set(reference_uniform);
+
+            bindings.orbit.upload(queue);
+            bindings.uniform.upload(queue);
+        }
+
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        let (pipeline, target) = match &self.terrain {
+            Some(terrain) => (&self.height_pipeline, terrain.get_heights()),
+            None => (&self.pipeline, target),
+        };
+
+        {
+            let mut render_pass = Scene::begin_render_pass(
+                target,
+                &mut encoder,
+                (index == 0).then_some(background_color),
+            );
+
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, self.params.get_bind_group(), &[]);
+            if let Some(bindings) = &self.reference {
+                render_pass.set_bind_group(1, bindings.bindings.get_bind_group(), &[]);
+            }
+            render_pass.draw(0..6, 0..1);
+        }
+
+        queue.submit([encoder.finish()]);
     }
 }
 
-fn build_pipeline(
+/// `source` with `custom_step`, a WGSL expression of `z` and `c`, returned
+/// by its `custom_step` function instead of the expression between the
+/// markers.
+fn splice_custom_step(source: &str, custom_step: &str) -> String {
+    let start = source
+        .find(CUSTOM_STEP_START)
+        .expect("Find the custom step start")
+        + CUSTOM_STEP_START.len();
+    let end = source
+        .find(CUSTOM_STEP_END)
+        .expect("Find the custom step end");
+
+    format!("{} {custom_step} {}", &source[..start], &source[end..])
+}
+
+/// Builds the pipelines drawing the colours and the heights of the terrain
+/// view, with `custom_step`, when there is one, as the step of the custom
+/// formula. Without the `reference` group the perturbation kernels are left
+/// out.
+fn build_pipelines(
     device: &wgpu::Device,
     texture_format: wgpu::TextureFormat,
-    viewport: &BindGroupLayout,
-    coordinates: &BindGroupLayout,
-    iterations: &BindGroupLayout,
-    color_params: &BindGroupLayout,
-) -> wgpu::RenderPipeline {
-    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-        label: Some("Shader"),
-        source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
-    });
-
-    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-        label: None,
-        push_constant_ranges: &[],
-        bind_group_layouts: &[viewport, coordinates, iterations, color_params],
-    });
-
-    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        label: None,
-        layout: Some(&pipeline_layout),
-        vertex: wgpu::VertexState {
-            module: &shader,
-            entry_point: Some("vs_main"),
-            buffers: &[],
-            compilation_options: wgpu::PipelineCompilationOptions::default(),
-        },
-        fragment: Some(wgpu::FragmentState {
-            module: &shader,
-            entry_point: Some("fs_main"),
-            targets: &[Some(wgpu::ColorTargetState {
-                format: texture_format,
-                blend: Some(wgpu::BlendState {
-                    color: wgpu::BlendComponent::REPLACE,
-                    alpha: wgpu::BlendComponent::REPLACE,
-                }),
-                write_mask: wgpu::ColorWrites::ALL,
-            })],
-            compilation_options: wgpu::PipelineCompilationOptions::default(),
-        }),
-        primitive: wgpu::PrimitiveState {
-            topology: wgpu::PrimitiveTopology::TriangleList,
-            front_face: wgpu::FrontFace::Ccw,
-            ..Default::default()
-        },
-        depth_stencil: None,
-        multisample: wgpu::MultisampleState {
-            count: 1,
-            mask: !0,
-            alpha_to_coverage_enabled: false,
-        },
-        multiview: None,
-        cache: None,
-    })
+    params: &Bindings,
+    reference: Option<&ReferenceBindings>,
+    custom_step: Option<&str>,
+) -> (wgpu::RenderPipeline, wgpu::RenderPipeline) {
+    let perturbation = match reference {
+        Some(_) => include_str!("perturbation.wgsl"),
+        None => include_str!("perturbation_fallback.wgsl"),
+    };
+    let shader = include_str!("shader.wgsl");
+    let source = match custom_step {
+        Some(custom_step) => splice_custom_step(shader, custom_step),
+        None => shader.to_owned(),
+    } + perturbation;
+    let bindings: Vec<&Bindings> = std::iter::once(params)
+        .chain(reference.map(|reference| &reference.bindings))
+        .collect();
+    let shader = pipeline::shader_module(device, "Shader", &source);
+    let pipeline_layout = pipeline::pipeline_layout(device, &bindings);
+
+    // Float textures can't be blended
+    let height_target = wgpu::ColorTargetState {
+        format: terrain::HEIGHT_FORMAT,
+        blend: None,
+        write_mask: wgpu::ColorWrites::ALL,
+    };
+
+    (
+        pipeline::render_pipeline(
+            device,
+            "fs_main",
+            &pipeline_layout,
+            &shader,
+            ("vs_main", "fs_main"),
+            pipeline::replace_target(texture_format),
+            None,
+        ),
+        pipeline::render_pipeline(
+            device,
+            "fs_height",
+            &pipeline_layout,
+            &shader,
+            ("vs_main", "fs_height"),
+            height_target,
+            None,
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splices_the_custom_step_between_the_markers() {
+        let source = splice_custom_step(include_str!("shader.wgsl"), "complex_sin(z) + c");
+
+        assert!(source.contains("/* custom_step { */ complex_sin(z) + c /* } custom_step */"));
+        assert!(!source.contains("complex_mul(z, z) + c /* }"));
+        assert_eq!(source.matches(CUSTOM_STEP_START).count(), 1);
+    }
+
+    #[test]
+    fn leaves_the_same_expression_elsewhere_alone() {
+        // The default step is also written out in other formulas
+        let source = splice_custom_step(include_str!("shader.wgsl"), "complex_mul(z, z) + c");
+
+        assert_eq!(source, include_str!("shader.wgsl"));
+    }
 }
@@ -1,127 +1,1291 @@
-struct Viewport {
-    half_viewport_x: f32,
-    half_viewport_y: f32,
-    ratio: f32,
-};
-
 struct Coordinates {
     real: f32,
+    real_lo: f32,
     imag: f32,
+    imag_lo: f32,
     zoom: f32,
+    zoom_lo: f32,
+    zoom_mantissa: f32,
+    zoom_exponent: i32,
+    kernel: i32,
+};
+
+// Extended-range complex value, mantissa * 2^exponent with one exponent shared
+// by both parts, for deltas too small for f32.
+struct ComplexExp {
+    mantissa: vec2<f32>,
+    exponent: i32,
 };
 
+const KERNEL_DOUBLE_FLOAT: i32 = 1;
+const KERNEL_PERTURBATION: i32 = 2;
+const KERNEL_PERTURBATION_FLOAT_EXP: i32 = 3;
+
 struct Iterations {
     value: i32
 };
 
-struct ColorParams {
-    hue: f32,
-    saturation: f32,
-    lightness: f32,
-    hue_linked: i32,
-    saturation_linked: i32,
-    lightness_linked: i32,
+struct FractalParams {
+    julia: i32,
+    julia_real: f32,
+    julia_real_lo: f32,
+    julia_imag: f32,
+    julia_imag_lo: f32,
+    exponent: f32,
+    escape_radius: f32,
+    formula: i32,
+    bailout_radius: f32,
+    bailout_norm: i32,
+    phoenix_real: f32,
+    phoenix_imag: f32,
+    relaxation: f32,
+};
+
+// Norm of z compared to the escape radius
+const NORM_EUCLIDEAN: i32 = 0;
+const NORM_MAXIMUM: i32 = 1;
+const NORM_REAL: i32 = 2;
+const NORM_IMAGINARY: i32 = 3;
+const NORM_MANHATTAN: i32 = 4;
+// Orbits past this |z|^2 escape whatever the norm, before they overflow
+const NORM_OVERFLOW: f32 = 1e30;
+
+const FORMULA_MANDELBROT: i32 = 0;
+const FORMULA_BURNING_SHIP: i32 = 1;
+const FORMULA_TRICORN: i32 = 2;
+const FORMULA_CELTIC: i32 = 3;
+const FORMULA_PERPENDICULAR_MANDELBROT: i32 = 4;
+const FORMULA_PERPENDICULAR_BURNING_SHIP: i32 = 5;
+const FORMULA_PERPENDICULAR_CELTIC: i32 = 6;
+const FORMULA_NEWTON: i32 = 7;
+const FORMULA_CUSTOM: i32 = 8;
+const FORMULA_LYAPUNOV: i32 = 9;
+const FORMULA_PHOENIX: i32 = 10;
+const FORMULA_MAGNET_1: i32 = 11;
+const FORMULA_MAGNET_2: i32 = 12;
+const FORMULA_NOVA: i32 = 13;
+const FORMULA_SINE: i32 = 14;
+const FORMULA_COSINE: i32 = 15;
+const FORMULA_EXPONENTIAL: i32 = 16;
+const FORMULA_LAMBDA: i32 = 17;
+const FORMULA_HYBRID: i32 = 18;
+
+// Imaginary part of z for the sine and cosine, real part for the exponential,
+// past which their orbits escape. Low enough for |z|^2 to stay finite.
+const TRANSCENDENTAL_BAILOUT: f32 = 40.0;
+
+const COLORING_ITERATIONS: i32 = 0;
+const COLORING_DISTANCE: i32 = 1;
+const COLORING_DISTANCE_MIX: i32 = 2;
+const COLORING_ORBIT_TRAP: i32 = 3;
+const COLORING_STRIPE_AVERAGE: i32 = 4;
+const COLORING_TRIANGLE_INEQUALITY: i32 = 5;
+
+// Shape the orbits are measured against, centered on real + imag i. The size
+// is the radius of the circle, and the distance at which pixels turn black.
+struct TrapParams {
+    shape: i32,
+    real: f32,
+    imag: f32,
+    size: f32,
+};
+
+const TRAP_POINT: i32 = 0;
+// Parallel to the real axis
+const TRAP_LINE: i32 = 1;
+const TRAP_CROSS: i32 = 2;
+const TRAP_CIRCLE: i32 = 3;
+
+// Directional light over the exterior, seen as a surface rising away from
+// the set. The angles are in degrees, the azimuth from the real axis.
+struct LightParams {
+    enabled: i32,
+    azimuth: f32,
+    elevation: f32,
+    intensity: f32,
+};
+
+// Roots are in xy, arrays in uniforms have a 16 byte stride
+struct NewtonParams {
+    roots: array<vec4<f32>, 16>,
+    root_count: i32,
+};
+
+// One bit per step of the sequence, set where the rate is b
+struct LyapunovParams {
+    sequence: vec4<u32>,
+    length: i32,
+};
+
+// Formula of each step of the hybrid, four to a vector
+struct HybridParams {
+    formulas: array<vec4<i32>, 8>,
+    length: i32,
 };
 
 @group(0) @binding(0)
 var<uniform> viewport: Viewport;
 
-@group(1) @binding(0)
+@group(0) @binding(1)
 var<uniform> coordinates: Coordinates;
 
-@group(2) @binding(0)
+@group(0) @binding(2)
 var<uniform> max_iterations: Iterations;
 
-@group(3) @binding(0)
+@group(0) @binding(3)
 var<uniform> color_params: ColorParams;
 
-@vertex
-fn vs_main(@builtin(vertex_index) vertex_idx: u32) -> @builtin(position) vec4<f32> {
-    var pos = vec2<f32>(0.0, 0.0);
-    switch vertex_idx {
-        case 0u: {
-            pos.x = 1.0;
-            pos.y = 1.0;
+// Iterates formula_step(z) + c. In Julia mode z starts at the pixel and c is
+// julia_real + julia_imag i
+@group(0) @binding(4)
+var<uniform> fractal_params: FractalParams;
+
+@group(0) @binding(5)
+var<uniform> newton_params: NewtonParams;
+
+@group(0) @binding(6)
+var<uniform> trap_params: TrapParams;
+
+@group(0) @binding(7)
+var<uniform> light_params: LightParams;
+
+@group(0) @binding(8)
+var<uniform> lyapunov_params: LyapunovParams;
+
+@group(0) @binding(9)
+var<uniform> hybrid_params: HybridParams;
+
+// End of a pixel's orbit: the iterations before it escaped, max_iterations
+// if it didn't, |z|^2 once past the bailout, the distance to the set in
+// pixels, NO_DISTANCE when it isn't estimated, the test that found the
+// pixel inside the set without iterating all the way, the closest the
+// orbit came to the trap, the average of the averaging colourings and the
+// direction of the lighting normal, zero when there is none.
+struct Escape {
+    iterations: i32,
+    dist: f32,
+    distance: f32,
+    interior: i32,
+    trap: f32,
+    average: OrbitAverage,
+    normal: vec2<f32>,
+};
+
+// Sum of the per iteration terms of the averaging colourings, and the last
+// one, to also know the average without it.
+struct OrbitAverage {
+    sum: f32,
+    last: f32,
+    count: i32,
+};
+
+const NO_DISTANCE: f32 = -1.0;
+// Trap distance of orbits that weren't measured, far enough to be black
+const NO_TRAP: f32 = 1e30;
+
+const INTERIOR_NONE: i32 = 0;
+const INTERIOR_CARDIOID: i32 = 1;
+const INTERIOR_BULB: i32 = 2;
+const INTERIOR_PERIODIC: i32 = 3;
+
+// Squared distance, over the zoom, under which an orbit is taken as having
+// come back to a previous point, so cycling forever. Exterior orbits near a
+// parabolic point c0, like the cardioid cusp at 1/4, crawl by about |c - c0|
+// per cycle before escaping, so a fixed distance would mark them interior
+// for a whole band of pixels around c0. A millionth of the view only catches
+// the ones within a millionth of it, well under a pixel.
+const PERIODICITY_TOLERANCE: f32 = 1e-12;
+
+// How far inside the cardioid and bulb, in the terms of their tests, the
+// deep zoom kernels need a point to be. Their views can sit on the edge,
+// closer than the f32 rounding of the tests.
+const INTERIOR_MARGIN: f32 = 1e-5;
+
+// Exponent given to zero, low enough that adding it to anything else leaves
+// it unchanged
+const CX_ZERO_EXPONENT: i32 = -1000000;
+
+fn transform_position(in: vec2<f32>) -> vec2<f32> {
+    var x = (in.x - viewport.half_viewport_x) / viewport.half_viewport_x * viewport.ratio;
+    var y = (in.y - viewport.half_viewport_y) / viewport.half_viewport_y * -1.0;
+
+    x = x * coordinates.zoom + coordinates.real;
+    y = y * coordinates.zoom + coordinates.imag;
+
+    return vec2(x, y);
+}
+
+// Double-float arithmetic: a value is the unevaluated sum hi + lo of two f32,
+// stored as vec2(hi, lo), which gives roughly 48 bits of mantissa.
+fn two_sum(a: f32, b: f32) -> vec2<f32> {
+    let s = a + b;
+    let v = s - a;
+    let err = (a - (s - v)) + (b - v);
+
+    return vec2(s, err);
+}
+
+fn quick_two_sum(a: f32, b: f32) -> vec2<f32> {
+    let s = a + b;
+    let err = b - (s - a);
+
+    return vec2(s, err);
+}
+
+fn split(a: f32) -> vec2<f32> {
+    // 2^12 + 1, splits the 24 bit mantissa into two halves
+    let t = 4097.0 * a;
+    let hi = t - (t - a);
+
+    return vec2(hi, a - hi);
+}
+
+fn two_prod(a: f32, b: f32) -> vec2<f32> {
+    let p = a * b;
+    let a_split = split(a);
+    let b_split = split(b);
+    let err = ((a_split.x * b_split.x - p) + a_split.x * b_split.y + a_split.y * b_split.x) + a_split.y * b_split.y;
+
+    return vec2(p, err);
+}
+
+fn df_add(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    var s = two_sum(a.x, b.x);
+    let t = two_sum(a.y, b.y);
+    s.y += t.x;
+    s = quick_two_sum(s.x, s.y);
+    s.y += t.y;
+
+    return quick_two_sum(s.x, s.y);
+}
+
+fn df_sub(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    return df_add(a, -b);
+}
+
+fn df_mul(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    var p = two_prod(a.x, b.x);
+    p.y += a.x * b.y + a.y * b.x;
+
+    return quick_two_sum(p.x, p.y);
+}
+
+fn df_mul_f32(a: vec2<f32>, b: f32) -> vec2<f32> {
+    var p = two_prod(a.x, b);
+    p.y += a.y * b;
+
+    return quick_two_sum(p.x, p.y);
+}
+
+struct DoubleComplex {
+    real: vec2<f32>,
+    imag: vec2<f32>,
+};
+
+fn transform_position_df(in: vec2<f32>) -> DoubleComplex {
+    let x = (in.x - viewport.half_viewport_x) / viewport.half_viewport_x * viewport.ratio;
+    let y = (in.y - viewport.half_viewport_y) / viewport.half_viewport_y * -1.0;
+
+    let zoom = vec2(coordinates.zoom, coordinates.zoom_lo);
+    let center_x = vec2(coordinates.real, coordinates.real_lo);
+    let center_y = vec2(coordinates.imag, coordinates.imag_lo);
+
+    return DoubleComplex(
+        df_add(df_mul_f32(zoom, x), center_x),
+        df_add(df_mul_f32(zoom, y), center_y),
+    );
+}
+
+fn df_complex_mul(a: DoubleComplex, b: DoubleComplex) -> DoubleComplex {
+    return DoubleComplex(
+        df_sub(df_mul(a.real, b.real), df_mul(a.imag, b.imag)),
+        df_add(df_mul(a.real, b.imag), df_mul(a.imag, b.real)),
+    );
+}
+
+fn df_abs(a: vec2<f32>) -> vec2<f32> {
+    return select(a, -a, a.x < 0.0);
+}
+
+// Same as formula_step. Only integer exponents reach this kernel
+fn df_formula_step(formula: i32, z: DoubleComplex) -> DoubleComplex {
+    if formula == FORMULA_MANDELBROT {
+        var power = z;
+        for (var i = 1; i < i32(fractal_params.exponent); i++) {
+            power = df_complex_mul(power, z);
         }
-        case 1u, 5u: {
-            pos.x = -1.0;
-            pos.y = 1.0;
+
+        return power;
+    }
+
+    var w = z;
+    if abs_z_real(formula) {
+        w.real = df_abs(w.real);
+    }
+    if abs_z_imag(formula) {
+        w.imag = df_abs(w.imag);
+    }
+
+    var square = df_complex_mul(w, w);
+    if abs_square_real(formula) {
+        square.real = df_abs(square.real);
+    }
+    if conjugate_square(formula) {
+        square.imag = -square.imag;
+    }
+
+    return square;
+}
+
+// Checks the interior and cycles like get_iterations, the cycles relative to
+// the zoom.
+fn get_iterations_df(z: DoubleComplex, c: DoubleComplex) -> Escape {
+    let interior = interior_test(vec2(c.real.x, c.imag.x), INTERIOR_MARGIN);
+    if interior != INTERIOR_NONE {
+        return interior_escape(interior, NO_TRAP, OrbitAverage());
+    }
+
+    var value = z;
+
+    var saved = value;
+    var saved_at = 1;
+
+    var iterations = 0;
+    var dist = 0.0;
+    var derivative = vec2(coordinates.zoom, 0.0);
+    let derivative_c = select(derivative, vec2(0.0), fractal_params.julia > 0);
+    let track_derivative = tracks_derivative();
+    var trap = NO_TRAP;
+    let track_trap = color_params.coloring == COLORING_ORBIT_TRAP;
+    var average = OrbitAverage();
+    let track_average = tracks_average();
+
+    while iterations < max_iterations.value {
+        if track_derivative {
+            let z = vec2(value.real.x, value.imag.x);
+            derivative = complex_mul(formula_derivative(z), derivative) + derivative_c;
         }
-        case 2u, 3u: {
-            pos.x = 1.0;
-            pos.y = -1.0;
+
+        let step = df_formula_step(step_formula(iterations), value);
+        value = DoubleComplex(df_add(step.real, c.real), df_add(step.imag, c.imag));
+
+        dist = value.real.x * value.real.x + value.imag.x * value.imag.x;
+        if track_trap {
+            trap = min(trap, trap_distance(vec2(value.real.x, value.imag.x)));
         }
-        case 4u: {
-            pos.x = -1.0;
-            pos.y = -1.0;
+        if track_average {
+            let z = vec2(value.real.x, value.imag.x);
+            average = add_average_term(average, z, vec2(c.real.x, c.imag.x));
+        }
+
+        if escapes(vec2(value.real.x, value.imag.x), dist) {
+            break;
+        }
+
+        let cycle = vec2(df_sub(value.real, saved.real).x, df_sub(value.imag, saved.imag).x) / coordinates.zoom;
+        if dot(cycle, cycle) < PERIODICITY_TOLERANCE {
+            return interior_escape(INTERIOR_PERIODIC, trap, average);
+        }
+
+        iterations += 1;
+
+        if iterations == saved_at {
+            saved = value;
+            saved_at *= 2;
         }
-        default: {}
     }
 
-    return vec4<f32>(pos, 0.0, 1.0);
+    return orbit_escape(
+        iterations,
+        vec2(value.real.x, value.imag.x),
+        dist,
+        cx_normalize(derivative, 0),
+        trap,
+        average,
+    );
 }
 
-fn transform_position(in: vec2<f32>) -> vec2<f32> {
-    var x = (in.x - viewport.half_viewport_x) / viewport.half_viewport_x * viewport.ratio;
-    var y = (in.y - viewport.half_viewport_y) / viewport.half_viewport_y * -1.0;
+fn complex_mul(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    return vec2(a.x * b.x - a.y * b.y, a.x * b.y + a.y * b.x);
+}
 
-    let center_x = coordinates.real - 0.4;
+// z^n, by repeated multiplication for integer powers and through the polar
+// form otherwise.
+fn complex_pow(z: vec2<f32>, n: f32) -> vec2<f32> {
+    if n == round(n) {
+        var power = z;
+        for (var i = 1; i < i32(n); i++) {
+            power = complex_mul(power, z);
+        }
 
-    x = x * coordinates.zoom + center_x;
-    y = y * coordinates.zoom - coordinates.imag;
+        return power;
+    }
 
-    return vec2(x, y);
+    let norm_squared = dot(z, z);
+    if norm_squared == 0.0 {
+        return vec2(0.0);
+    }
+
+    let angle = atan2(z.y, z.x) * n;
+
+    return pow(norm_squared, n * 0.5) * vec2(cos(angle), sin(angle));
+}
+
+// The other formulas square z like the Mandelbrot one after taking the
+// absolute value of some of its parts, then take the absolute value of the
+// real part of the square or conjugate it.
+fn abs_z_real(formula: i32) -> bool {
+    return formula == FORMULA_BURNING_SHIP || formula == FORMULA_PERPENDICULAR_MANDELBROT
+        || formula == FORMULA_PERPENDICULAR_CELTIC;
+}
+
+fn abs_z_imag(formula: i32) -> bool {
+    return formula == FORMULA_BURNING_SHIP || formula == FORMULA_PERPENDICULAR_BURNING_SHIP;
+}
+
+fn abs_square_real(formula: i32) -> bool {
+    return formula == FORMULA_CELTIC || formula == FORMULA_PERPENDICULAR_CELTIC;
+}
+
+// Flips the Burning Ship and the perpendicular sets upright
+fn conjugate_square(formula: i32) -> bool {
+    return formula != FORMULA_MANDELBROT && formula != FORMULA_CELTIC;
+}
+
+// z through the formula, before c is added
+fn formula_step(formula: i32, z: vec2<f32>) -> vec2<f32> {
+    if formula == FORMULA_MANDELBROT {
+        return complex_pow(z, fractal_params.exponent);
+    }
+
+    let w = select(z, abs(z), vec2(abs_z_real(formula), abs_z_imag(formula)));
+    var square = complex_mul(w, w);
+    if abs_square_real(formula) {
+        square.x = abs(square.x);
+    }
+    if conjugate_square(formula) {
+        square.y = -square.y;
+    }
+
+    return square;
+}
+
+// |c + d * 2^shift| - |c|, scaled by 2^-shift, without the cancellation of
+// subtracting the two. For c = 0 that is |d|, whose sign the sum loses when
+// d * 2^shift underflows.
+fn diffabs_scaled(c: f32, d: f32, shift: i32) -> f32 {
+    if c == 0.0 {
+        return abs(d);
+    }
+
+    let sum = c + scaled(vec2(d, 0.0), shift).x;
+    let twice_c = scaled(vec2(2.0 * c, 0.0), -shift).x;
+
+    if c >= 0.0 {
+        return select(-d - twice_c, d, sum >= 0.0);
+    }
+
+    return select(-d, d + twice_c, sum > 0.0);
+}
+
+// formula_step(Z + dz) - formula_step(Z) for all formulas but the Mandelbrot
+// one, with dz and the result scaled by 2^-shift so deltas past the f32 range
+// keep their precision.
+fn formula_delta(formula: i32, reference_z: vec2<f32>, delta_z: vec2<f32>, shift: i32) -> vec2<f32> {
+    let abs_z = vec2(abs_z_real(formula), abs_z_imag(formula));
+    let w = select(reference_z, abs(reference_z), abs_z);
+    let delta_w = select(
+        delta_z,
+        vec2(
+            diffabs_scaled(reference_z.x, delta_z.x, shift),
+            diffabs_scaled(reference_z.y, delta_z.y, shift),
+        ),
+        abs_z,
+    );
+
+    // (w + dw)^2 - w^2
+    var delta = 2.0 * complex_mul(w, delta_w) + scaled(complex_mul(delta_w, delta_w), shift);
+    if abs_square_real(formula) {
+        delta.x = diffabs_scaled(w.x * w.x - w.y * w.y, delta.x, shift);
+    }
+    if conjugate_square(formula) {
+        delta.y = -delta.y;
+    }
+
+    return delta;
+}
+
+// Complex functions the custom formulas are compiled to, see expression.rs
+fn complex_div(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    return vec2(a.x * b.x + a.y * b.y, a.y * b.x - a.x * b.y) / dot(b, b);
+}
+
+// a^b through the principal logarithm, 0^b taken as 0
+fn complex_cpow(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    if dot(a, a) == 0.0 {
+        return vec2(0.0);
+    }
+
+    return complex_exp(complex_mul(b, complex_log(a)));
+}
+
+fn complex_exp(z: vec2<f32>) -> vec2<f32> {
+    return exp(z.x) * vec2(cos(z.y), sin(z.y));
+}
+
+fn complex_log(z: vec2<f32>) -> vec2<f32> {
+    return vec2(log(length(z)), atan2(z.y, z.x));
+}
+
+fn complex_sqrt(z: vec2<f32>) -> vec2<f32> {
+    let norm = length(z);
+    let imag = sqrt(max(norm - z.x, 0.0) * 0.5);
+
+    return vec2(sqrt(max(norm + z.x, 0.0) * 0.5), select(imag, -imag, z.y < 0.0));
+}
+
+fn complex_sin(z: vec2<f32>) -> vec2<f32> {
+    return vec2(sin(z.x) * cosh(z.y), cos(z.x) * sinh(z.y));
+}
+
+fn complex_cos(z: vec2<f32>) -> vec2<f32> {
+    return vec2(cos(z.x) * cosh(z.y), -sin(z.x) * sinh(z.y));
+}
+
+fn complex_tan(z: vec2<f32>) -> vec2<f32> {
+    return complex_div(complex_sin(z), complex_cos(z));
+}
+
+fn complex_sinh(z: vec2<f32>) -> vec2<f32> {
+    return vec2(sinh(z.x) * cos(z.y), cosh(z.x) * sin(z.y));
+}
+
+fn complex_cosh(z: vec2<f32>) -> vec2<f32> {
+    return vec2(cosh(z.x) * cos(z.y), sinh(z.x) * sin(z.y));
+}
+
+fn complex_tanh(z: vec2<f32>) -> vec2<f32> {
+    return complex_div(complex_sinh(z), complex_cosh(z));
+}
+
+fn complex_abs(z: vec2<f32>) -> vec2<f32> {
+    return vec2(length(z), 0.0);
+}
+
+fn complex_conj(z: vec2<f32>) -> vec2<f32> {
+    return vec2(z.x, -z.y);
+}
+
+fn complex_re(z: vec2<f32>) -> vec2<f32> {
+    return vec2(z.x, 0.0);
+}
+
+fn complex_im(z: vec2<f32>) -> vec2<f32> {
+    return vec2(z.y, 0.0);
+}
+
+// The formula typed in the controls. The scene replaces the expression
+// between the markers with the compiled one when building the pipeline.
+fn custom_step(z: vec2<f32>, c: vec2<f32>) -> vec2<f32> {
+    return /* custom_step { */ complex_mul(z, z) + c /* } custom_step */;
+}
+
+// Squared length of the last step under which the orbits of the Magnet and
+// Nova formulas have converged, to 1 for the Magnet ones
+const CONVERGENCE_TOLERANCE: f32 = 1e-6;
+
+fn converges() -> bool {
+    let formula = fractal_params.formula;
+
+    return formula == FORMULA_MAGNET_1 || formula == FORMULA_MAGNET_2 || formula == FORMULA_NOVA;
+}
+
+// Orbits of the Mandelbrot-like sets start at a critical point of the
+// formula. Passing c as z starts most one iteration in, the critical value
+// being c, but the rational ones and the lambda map need theirs.
+fn orbit_start(z: vec2<f32>) -> vec2<f32> {
+    if fractal_params.julia > 0 {
+        return z;
+    }
+
+    switch fractal_params.formula {
+        case FORMULA_MAGNET_1, FORMULA_MAGNET_2: {
+            return vec2(0.0);
+        }
+        case FORMULA_NOVA: {
+            return vec2(1.0, 0.0);
+        }
+        case FORMULA_LAMBDA: {
+            return vec2(0.5, 0.0);
+        }
+        default: {
+            return z;
+        }
+    }
+}
+
+// Formula of the iteration, which a hybrid cycles through its steps for
+fn step_formula(iteration: i32) -> i32 {
+    if fractal_params.formula != FORMULA_HYBRID {
+        return fractal_params.formula;
+    }
+
+    let step = iteration % hybrid_params.length;
+
+    return hybrid_params.formulas[step / 4][step % 4];
+}
+
+// One iteration of the formula from z, with previous the point before it.
+// Phoenix adds p times the previous point to z^2 + c. Magnet I and II are
+// the squared rational maps of the magnetic models, with 1 as a fixed point.
+// Nova is Newton's method on z^3 - 1, relaxed, plus c. The transcendental
+// maps and the lambda one multiply their function of z by c.
+fn iterate(formula: i32, z: vec2<f32>, previous: vec2<f32>, c: vec2<f32>) -> vec2<f32> {
+    let one = vec2(1.0, 0.0);
+
+    switch formula {
+        case FORMULA_CUSTOM: {
+            return custom_step(z, c);
+        }
+        case FORMULA_PHOENIX: {
+            let p = vec2(fractal_params.phoenix_real, fractal_params.phoenix_imag);
+
+            return complex_mul(z, z) + c + complex_mul(p, previous);
+        }
+        case FORMULA_MAGNET_1: {
+            let ratio = complex_div(complex_mul(z, z) + c - one, 2.0 * z + c - 2.0 * one);
+
+            return complex_mul(ratio, ratio);
+        }
+        case FORMULA_MAGNET_2: {
+            let square = complex_mul(z, z);
+            let c1 = c - one;
+            let c2 = c - 2.0 * one;
+            let c12 = complex_mul(c1, c2);
+            let ratio = complex_div(
+                complex_mul(square, z) + 3.0 * complex_mul(c1, z) + c12,
+                3.0 * square + 3.0 * complex_mul(c2, z) + c12 + one,
+            );
+
+            return complex_mul(ratio, ratio);
+        }
+        case FORMULA_NOVA: {
+            let square = complex_mul(z, z);
+            let newton = complex_div(complex_mul(square, z) - one, 3.0 * square);
+
+            return z - fractal_params.relaxation * newton + c;
+        }
+        case FORMULA_SINE: {
+            return complex_mul(c, complex_sin(z));
+        }
+        case FORMULA_COSINE: {
+            return complex_mul(c, complex_cos(z));
+        }
+        case FORMULA_EXPONENTIAL: {
+            return complex_mul(c, complex_exp(z));
+        }
+        case FORMULA_LAMBDA: {
+            return complex_mul(c, complex_mul(z, one - z));
+        }
+        default: {
+            return formula_step(formula, z) + c;
+        }
+    }
+}
+
+// The kernels track the derivative of z for the distance estimate and the
+// lighting only when they are shown, and for the Mandelbrot formula, the only
+// analytic one of the built in formulas.
+fn tracks_derivative() -> bool {
+    let coloring = color_params.coloring;
+
+    return (coloring == COLORING_DISTANCE || coloring == COLORING_DISTANCE_MIX
+        || light_params.enabled > 0)
+        && fractal_params.formula == FORMULA_MANDELBROT;
+}
+
+// Derivative of the Mandelbrot formula's step, n z^(n - 1)
+fn formula_derivative(z: vec2<f32>) -> vec2<f32> {
+    return fractal_params.exponent * complex_pow(z, fractal_params.exponent - 1.0);
+}
+
+// Distance from an escaped point to the set, |z| log|z| / |dz/du|, halfway
+// between the bounds of the estimate, in pixels given the derivative with
+// respect to the screen-relative position u.
+fn distance_estimate(dist: f32, derivative: ComplexExp) -> f32 {
+    let norm = sqrt(dist);
+    let estimate = norm * log(norm) / length(derivative.mantissa) * viewport.half_viewport_y;
+
+    return ldexp(estimate, clamp(-derivative.exponent, -126, 127));
+}
+
+fn orbit_escape(
+    iterations: i32,
+    z: vec2<f32>,
+    dist: f32,
+    derivative: ComplexExp,
+    trap: f32,
+    average: OrbitAverage,
+) -> Escape {
+    var distance = NO_DISTANCE;
+    var normal = vec2(0.0);
+    if tracks_derivative() && iterations < max_iterations.value {
+        distance = distance_estimate(dist, derivative);
+
+        // Direction of z / dz, which the exponent of dz doesn't change
+        let direction = complex_mul(z, vec2(derivative.mantissa.x, -derivative.mantissa.y));
+        if dot(direction, direction) > 0.0 {
+            normal = normalize(direction);
+        }
+    }
+
+    return Escape(iterations, dist, distance, INTERIOR_NONE, trap, average, normal);
+}
+
+// Extended-range arithmetic. Mantissas are kept with their largest part in
+// [0.5, 1), so products never leave the f32 range.
+fn cx_normalize(mantissa: vec2<f32>, exponent: i32) -> ComplexExp {
+    let largest = max(abs(mantissa.x), abs(mantissa.y));
+    if largest == 0.0 {
+        return ComplexExp(vec2(0.0), CX_ZERO_EXPONENT);
+    }
+
+    let shift = frexp(largest).exp;
+
+    return ComplexExp(ldexp(mantissa, vec2(-shift)), exponent + shift);
+}
+
+fn cx_add(a: ComplexExp, b: ComplexExp) -> ComplexExp {
+    let exponent = max(a.exponent, b.exponent);
+
+    return cx_normalize(cx_scaled(a, -exponent) + cx_scaled(b, -exponent), exponent);
+}
+
+fn cx_mul_vec2(a: ComplexExp, b: vec2<f32>) -> ComplexExp {
+    return cx_normalize(complex_mul(a.mantissa, b), a.exponent);
+}
+
+// a * 2^shift as a plain vec2
+fn cx_scaled(a: ComplexExp, shift: i32) -> vec2<f32> {
+    return scaled(a.mantissa, a.exponent + shift);
+}
+
+// v * 2^exponent, flushed to zero below the f32 range
+fn scaled(v: vec2<f32>, exponent: i32) -> vec2<f32> {
+    if exponent < -126 {
+        return vec2(0.0);
+    }
+
+    return ldexp(v, vec2(min(exponent, 127)));
+}
+
+fn cx_to_vec2(a: ComplexExp) -> vec2<f32> {
+    return cx_scaled(a, 0);
+}
+
+// Squared distance to a root at which Newton's method has reached it
+const NEWTON_TOLERANCE: f32 = 1e-6;
+// Iterations over which the colour of a root fades out
+const NEWTON_SHADING: f32 = 16.0;
+
+// Newton's method z - p(z) / p'(z) from z, with p'(z) / p(z) the sum of
+// 1 / (z - root) over the roots. Returns the index of the root reached, -1 if
+// none was, and the iterations it took.
+fn get_root_newton(z: vec2<f32>) -> vec2<i32> {
+    var value = z;
+
+    var iterations = 0;
+
+    while iterations < max_iterations.value {
+        var sum = vec2(0.0);
+        for (var i = 0; i < newton_params.root_count; i++) {
+            let offset = value - newton_params.roots[i].xy;
+            let dist = dot(offset, offset);
+
+            if dist < NEWTON_TOLERANCE {
+                return vec2(i, iterations);
+            }
+
+            sum += vec2(offset.x, -offset.y) / dist;
+        }
+
+        // No roots, or a critical point the method can't leave
+        let sum_squared = dot(sum, sum);
+        if sum_squared == 0.0 {
+            break;
+        }
+
+        value -= vec2(sum.x, -sum.y) / sum_squared;
+
+        iterations += 1;
+    }
+
+    return vec2(-1, iterations);
+}
+
+// Hue shifted by the root reached, darker the longer it took to get there
+fn get_root_color(root: vec2<i32>) -> vec4<f32> {
+    if root.x < 0 {
+        return vec4(0.0, 0.0, 0.0, 1.0);
+    }
+
+    let hue = color_params.hue + f32(root.x) / f32(newton_params.root_count);
+    let lightness = color_params.lightness * exp(-f32(root.y) / NEWTON_SHADING);
+
+    return vec4(hsl_to_rgb(vec3(hue, color_params.saturation, lightness)), 1.0);
 }
 
-fn get_iterations(c: vec2<f32>) -> i32 {
-    var real = c.x;
-    var imag = c.y;
+// Main cardioid and period 2 bulb of the Mandelbrot set, whose points all
+// end up inside, for points at least margin inside them. Skipped when the
+// orbits inside are coloured by the trap.
+fn interior_test(c: vec2<f32>, margin: f32) -> i32 {
+    if fractal_params.formula != FORMULA_MANDELBROT || fractal_params.exponent != 2.0
+        || fractal_params.julia > 0 || color_params.coloring == COLORING_ORBIT_TRAP {
+        return INTERIOR_NONE;
+    }
+
+    let x = c.x - 0.25;
+    let q = x * x + c.y * c.y;
+    if q * (q + x) <= 0.25 * c.y * c.y - margin {
+        return INTERIOR_CARDIOID;
+    }
+
+    let bulb_x = c.x + 1.0;
+    if bulb_x * bulb_x + c.y * c.y <= 0.0625 - margin {
+        return INTERIOR_BULB;
+    }
+
+    return INTERIOR_NONE;
+}
+
+// Escape of a pixel the test interior found inside the set
+fn interior_escape(interior: i32, trap: f32, average: OrbitAverage) -> Escape {
+    return Escape(max_iterations.value, 0.0, NO_DISTANCE, interior, trap, average, vec2(0.0));
+}
+
+// Also stops at orbits that come back to a point saved when the iterations
+// reached a power of two, Brent's cycle detection, which finds cycles of any
+// period soon after the orbit settles on them. The state of the Phoenix
+// formula is both z and the point before it, so both have to come back.
+fn get_iterations(z: vec2<f32>, c: vec2<f32>) -> Escape {
+    let interior = interior_test(c, 0.0);
+    if interior != INTERIOR_NONE {
+        return interior_escape(interior, NO_TRAP, OrbitAverage());
+    }
+
+    var value = orbit_start(z);
+    var previous = vec2(0.0);
+
+    var saved = value;
+    var saved_previous = previous;
+    var saved_at = 1;
 
     var iterations = 0;
-    var const_real = real;
-    var const_imag = imag;
+    var dist = 0.0;
+    // dz/du for the pixel at u in the screen-relative units of
+    // transform_position, which is dz/dc scaled by the zoom
+    var derivative = vec2(coordinates.zoom, 0.0);
+    let derivative_c = select(derivative, vec2(0.0), fractal_params.julia > 0);
+    let track_derivative = tracks_derivative();
+    var trap = NO_TRAP;
+    let track_trap = color_params.coloring == COLORING_ORBIT_TRAP;
+    var average = OrbitAverage();
+    let track_average = tracks_average();
 
     while iterations < max_iterations.value {
-        var tmp_real = real;
-        real = (real * real - imag * imag) + const_real;
-        imag = (2.0 * tmp_real * imag) + const_imag;
+        if track_derivative {
+            derivative = complex_mul(formula_derivative(value), derivative) + derivative_c;
+        }
 
-        var dist = real * real + imag * imag;
+        let next = iterate(step_formula(iterations), value, previous, c);
+        previous = value;
+        value = next;
 
-        if dist > 4.0 {
+        dist = dot(value, value);
+        if track_trap {
+            trap = min(trap, trap_distance(value));
+        }
+        if track_average {
+            average = add_average_term(average, value, c);
+        }
+
+        if escapes(value, dist) {
             break;
         }
 
+        // Kept as the distance, under the tolerance unlike escaped orbits
+        if converges() {
+            let limit = select(previous, vec2(1.0, 0.0), fractal_params.formula != FORMULA_NOVA);
+            let offset = value - limit;
+            if dot(offset, offset) < CONVERGENCE_TOLERANCE {
+                dist = dot(offset, offset);
+                break;
+            }
+        }
+
+        let cycle = (value - saved) / coordinates.zoom;
+        let cycle_previous = (previous - saved_previous) / coordinates.zoom;
+        if dot(cycle, cycle) + dot(cycle_previous, cycle_previous) < PERIODICITY_TOLERANCE {
+            return interior_escape(INTERIOR_PERIODIC, trap, average);
+        }
+
         iterations += 1;
+
+        if iterations == saved_at {
+            saved = value;
+            saved_previous = previous;
+            saved_at *= 2;
+        }
+    }
+
+    return orbit_escape(iterations, value, dist, cx_normalize(derivative, 0), trap, average);
+}
+
+// Continuous iteration count, n + 1 - log_p(log|z|) for an orbit escaping
+// after n iterations of a formula of power p, which doesn't band like n.
+// Custom formulas are taken as squaring z. Converged orbits get the same
+// count with the last step measured against the tolerance, as if they
+// converged quadratically.
+fn smooth_iterations(escape: Escape) -> f32 {
+    if escape.iterations >= max_iterations.value {
+        return f32(max_iterations.value);
+    }
+
+    // From about log(T) to T for the last point before the escape, which
+    // grows exponentially past the bailout T
+    if escapes_exponentially() {
+        let bound = log(TRANSCENDENTAL_BAILOUT);
+        let growth = (0.5 * log(escape.dist) - bound) / (TRANSCENDENTAL_BAILOUT - bound);
+
+        return max(f32(escape.iterations) + 1.0 - clamp(growth, 0.0, 1.0), 0.0);
+    }
+
+    if converges() && escape.dist < CONVERGENCE_TOLERANCE {
+        let ratio = log(max(escape.dist, 1e-30)) / log(CONVERGENCE_TOLERANCE);
+
+        return max(f32(escape.iterations) + 1.0 - log2(ratio), 0.0);
+    }
+
+    let power = select(2.0, fractal_params.exponent, fractal_params.formula == FORMULA_MANDELBROT);
+
+    let iterations = f32(escape.iterations) + 1.0 - log2(0.5 * log(escape.dist)) / log2(power);
+
+    return max(iterations, 0.0);
+}
+
+fn get_color(escape: Escape) -> vec4<f32> {
+    var iterations = f32(escape.iterations);
+    if color_params.smooth_color > 0 {
+        iterations = smooth_iterations(escape);
+    }
+
+    return vec4(hsl_color(iterations / f32(max_iterations.value), color_params), 1.0);
+}
+
+fn escapes_exponentially() -> bool {
+    let formula = fractal_params.formula;
+
+    return formula == FORMULA_SINE || formula == FORMULA_COSINE || formula == FORMULA_EXPONENTIAL;
+}
+
+// Whether z, of squared length dist, is past the escape radius in the norm
+// picked. The sine and cosine grow with the imaginary part of z and the
+// exponential with the real part, so they escape on those instead.
+fn escapes(z: vec2<f32>, dist: f32) -> bool {
+    if escapes_exponentially() {
+        let growth = select(abs(z.y), z.x, fractal_params.formula == FORMULA_EXPONENTIAL);
+
+        return growth > TRANSCENDENTAL_BAILOUT;
+    }
+
+    var norm: f32;
+    switch fractal_params.bailout_norm {
+        case NORM_MAXIMUM: {
+            norm = max(abs(z.x), abs(z.y));
+        }
+        case NORM_REAL: {
+            norm = abs(z.x);
+        }
+        case NORM_IMAGINARY: {
+            norm = abs(z.y);
+        }
+        case NORM_MANHATTAN: {
+            norm = abs(z.x) + abs(z.y);
+        }
+        default: {
+            return dist > fractal_params.escape_radius * fractal_params.escape_radius;
+        }
+    }
+
+    return norm > fractal_params.escape_radius || dist > NORM_OVERFLOW;
+}
+
+fn tracks_average() -> bool {
+    let coloring = color_params.coloring;
+
+    return coloring == COLORING_STRIPE_AVERAGE || coloring == COLORING_TRIANGLE_INEQUALITY;
+}
+
+// Adds the term of z, the orbit point that c was just added to.
+// The stripe term follows the argument of z. The triangle inequality one is
+// where |z| falls between the bounds |z - c| and |c| put on it, and is
+// skipped when they meet like on the first Mandelbrot iteration.
+fn add_average_term(average: OrbitAverage, z: vec2<f32>, c: vec2<f32>) -> OrbitAverage {
+    var term: f32;
+    if color_params.coloring == COLORING_STRIPE_AVERAGE {
+        term = 0.5 + 0.5 * sin(color_params.stripe_density * atan2(z.y, z.x));
+    } else {
+        let step = length(z - c);
+        let lower = abs(step - length(c));
+        let upper = step + length(c);
+        if upper - lower <= 0.0 {
+            return average;
+        }
+
+        term = (length(z) - lower) / (upper - lower);
+    }
+
+    return OrbitAverage(average.sum + term, term, average.count + 1);
+}
+
+// Average of the orbit terms, blended between the average without the last
+// term and with it by the smooth iteration fraction so it doesn't band.
+fn orbit_average(escape: Escape) -> f32 {
+    let average = escape.average;
+    if escape.iterations >= max_iterations.value || average.count == 0 {
+        return 1.0;
+    }
+
+    let last = average.sum / f32(average.count);
+    var previous = last;
+    if average.count > 1 {
+        previous = (average.sum - average.last) / f32(average.count - 1);
+    }
+    var value = mix(previous, last, fract(smooth_iterations(escape)));
+
+    if color_params.coloring == COLORING_TRIANGLE_INEQUALITY {
+        value = fract(value * color_params.triangle_density);
+    }
+
+    // 1 is the black of the inside of the set
+    return min(value, MAX_AVERAGE);
+}
+
+fn trap_distance(z: vec2<f32>) -> f32 {
+    let offset = z - vec2(trap_params.real, trap_params.imag);
+
+    switch trap_params.shape {
+        case TRAP_LINE: {
+            return abs(offset.y);
+        }
+        case TRAP_CROSS: {
+            return min(abs(offset.x), abs(offset.y));
+        }
+        case TRAP_CIRCLE: {
+            return abs(length(offset) - trap_params.size);
+        }
+        default: {
+            return length(offset);
+        }
+    }
+}
+
+// Pixels further than this from the set are fully lit in the distance
+// colourings
+const DISTANCE_FALLOFF: f32 = 4.0;
+const MAX_AVERAGE: f32 = 0.999;
+
+fn get_pixel_color(escape: Escape) -> vec4<f32> {
+    if color_params.coloring == COLORING_ORBIT_TRAP {
+        return vec4(hsl_color(escape.trap / trap_params.size, color_params), 1.0);
+    }
+    if tracks_average() {
+        return vec4(hsl_color(orbit_average(escape), color_params), 1.0);
     }
 
-    return iterations;
+    var color = get_color(escape);
+    if escape.distance == NO_DISTANCE {
+        return color;
+    }
+
+    let shade = sqrt(clamp(escape.distance / DISTANCE_FALLOFF, 0.0, 1.0));
+    switch color_params.coloring {
+        case COLORING_DISTANCE: {
+            color = vec4(vec3(shade), 1.0);
+        }
+        case COLORING_DISTANCE_MIX: {
+            color = vec4(color.rgb * shade, 1.0);
+        }
+        default: {}
+    }
+
+    return color;
+}
+
+// Lambert shading of the surface whose slope faces the normal direction,
+// blended into the colour by the intensity
+fn apply_lighting(color: vec4<f32>, normal: vec2<f32>) -> vec4<f32> {
+    if light_params.enabled == 0 || all(normal == vec2(0.0)) {
+        return color;
+    }
+
+    let azimuth = radians(light_params.azimuth);
+    let elevation = radians(light_params.elevation);
+    let light = vec3(cos(elevation) * vec2(cos(azimuth), sin(azimuth)), sin(elevation));
+    let surface = normalize(vec3(normal, 1.0));
+    let shade = max(dot(surface, light), 0.0);
+
+    return vec4(color.rgb * mix(1.0, shade, light_params.intensity), color.a);
+}
+
+// Debug colours of the interior tests: red for the cardioid, green for the
+// bulb, blue for periodicity
+fn interior_overlay_color(interior: i32) -> vec3<f32> {
+    switch interior {
+        case INTERIOR_CARDIOID: {
+            return vec3(1.0, 0.0, 0.0);
+        }
+        case INTERIOR_BULB: {
+            return vec3(0.0, 1.0, 0.0);
+        }
+        default: {
+            return vec3(0.0, 0.0, 1.0);
+        }
+    }
 }
 
-fn hsl_to_rgb(hsv: vec3<f32>) -> vec3<f32> {
-    let K = vec4(1.0, 2.0 / 3.0, 1.0 / 3.0, 3.0);
-    let p = abs(fract(hsv.xxx + K.xyz) * 6.0 - K.www);
+// Steps of the logistic map before its exponent is measured, so it settles
+// on its attractor first
+const LYAPUNOV_SETTLE: i32 = 100;
+// Colours of the stable regions, of negative exponent, and of the chaotic
+// ones
+const LYAPUNOV_STABLE: vec3<f32> = vec3(1.0, 0.85, 0.0);
+const LYAPUNOV_CHAOTIC: vec3<f32> = vec3(0.0, 0.2, 0.7);
+// Past this rate, or at rates of zero and below, the map leaves [0, 1] and
+// diverges, so those rates are left black
+const LYAPUNOV_MAX_RATE: f32 = 4.0;
+
+fn has_lyapunov_rates(rates: vec2<f32>) -> bool {
+    return all(rates > vec2(0.0)) && all(rates <= vec2(LYAPUNOV_MAX_RATE));
+}
 
-    let clamped = clamp(p - K.xxx, vec3(0.0), vec3(1.0));
+fn lyapunov_rate(rates: vec2<f32>, iteration: i32) -> f32 {
+    let index = u32(iteration % lyapunov_params.length);
+    let b = (lyapunov_params.sequence[index / 32u] >> (index % 32u)) & 1u;
 
-    return hsv.z * mix(K.xxx, clamped, hsv.y);
+    return select(rates.x, rates.y, b == 1u);
 }
 
-fn get_color(iterations: i32) -> vec4<f32> {
-    var iterations_color = f32(iterations) / f32(max_iterations.value);
+// Lyapunov exponent of the logistic map x = r x (1 - x), r following the
+// sequence through the rates a and b, the mean of log |r (1 - 2x)|
+fn get_lyapunov_exponent(rates: vec2<f32>) -> f32 {
+    var x = 0.5;
+    for (var i = 0; i < LYAPUNOV_SETTLE; i++) {
+        let rate = lyapunov_rate(rates, i);
+        x = rate * x * (1.0 - x);
+    }
+
+    var sum = 0.0;
+    let steps = max(max_iterations.value, 1);
+    for (var i = 0; i < steps; i++) {
+        let rate = lyapunov_rate(rates, LYAPUNOV_SETTLE + i);
+        sum += log(abs(rate * (1.0 - 2.0 * x)));
+        x = rate * x * (1.0 - x);
+    }
 
-    var hue = select(color_params.hue, iterations_color + color_params.hue - 1.0, color_params.hue_linked > 0);
-    var saturation = select(color_params.saturation, color_params.saturation * iterations_color, color_params.saturation_linked > 0);
-    var lightness = select(0.0, select(color_params.lightness, color_params.lightness * iterations_color, color_params.lightness_linked > 0), iterations_color < 1.0);
+    return sum / f32(steps);
+}
+
+// Black where the exponent is zero, on the edge of chaos, brightening
+// towards the colour of either side
+fn get_lyapunov_color(exponent: f32) -> vec4<f32> {
+    if exponent < 0.0 {
+        return vec4(LYAPUNOV_STABLE * (1.0 - exp(exponent)), 1.0);
+    }
 
-    return vec4(hsl_to_rgb(vec3(hue, saturation, lightness)), 1.0);
+    return vec4(LYAPUNOV_CHAOTIC * (1.0 - exp(-exponent)), 1.0);
+}
+
+// Escape of the pixel at in, from the kernel the zoom level picked. The
+// perturbation kernels also mark the glitched pixels, and in the passes of
+// the later references skip the others.
+fn get_escape(in: vec2<f32>) -> Escape {
+    var escape: Escape;
+    switch coordinates.kernel {
+        case KERNEL_PERTURBATION, KERNEL_PERTURBATION_FLOAT_EXP: {
+            escape = get_escape_perturbation(in);
+        }
+        case KERNEL_DOUBLE_FLOAT: {
+            let position = transform_position_df(in);
+            var constant = position;
+            if fractal_params.julia > 0 {
+                constant = DoubleComplex(
+                    vec2(fractal_params.julia_real, fractal_params.julia_real_lo),
+                    vec2(fractal_params.julia_imag, fractal_params.julia_imag_lo),
+                );
+            }
+
+            escape = get_iterations_df(position, constant);
+        }
+        default: {
+            let position = transform_position(in);
+            let constant = select(
+                position,
+                vec2(fractal_params.julia_real, fractal_params.julia_imag),
+                fractal_params.julia > 0,
+            );
+
+            escape = get_iterations(position, constant);
+        }
+    }
+
+    return escape;
 }
 
 @fragment
 fn fs_main(@builtin(position) in: vec4<f32>) -> @location(0) vec4<f32> {
-    let position = transform_position(in.xy);
+    // Only the single precision kernel draws them
+    if fractal_params.formula == FORMULA_NEWTON {
+        return get_root_color(get_root_newton(transform_position(in.xy)));
+    }
+    if fractal_params.formula == FORMULA_LYAPUNOV {
+        let rates = transform_position(in.xy);
+        if !has_lyapunov_rates(rates) {
+            return vec4(0.0, 0.0, 0.0, 1.0);
+        }
+
+        return get_lyapunov_color(get_lyapunov_exponent(rates));
+    }
 
-    let iterations = get_iterations(position);
+    let escape = get_escape(in.xy);
 
-    return get_color(iterations);
-}
\ No newline at end of file
+    var color = apply_lighting(get_pixel_color(escape), escape.normal);
+    if color_params.interior_overlay > 0 && escape.interior != INTERIOR_NONE {
+        color = vec4(mix(color.rgb, interior_overlay_color(escape.interior), 0.5), 1.0);
+    }
+
+    return color;
+}
+
+// Smooth iteration count of the pixel, the height of the terrain view. For
+// Newton's method the iterations to a root, for the Lyapunov fractal its
+// stability scaled to the iteration limit.
+@fragment
+fn fs_height(@builtin(position) in: vec4<f32>) -> @location(0) f32 {
+    let limit = f32(max_iterations.value);
+    if fractal_params.formula == FORMULA_NEWTON {
+        let root = get_root_newton(transform_position(in.xy));
+
+        return select(f32(root.y), limit, root.x < 0);
+    }
+    if fractal_params.formula == FORMULA_LYAPUNOV {
+        let rates = transform_position(in.xy);
+        if !has_lyapunov_rates(rates) {
+            return 0.0;
+        }
+
+        return (0.5 - 0.5 * tanh(get_lyapunov_exponent(rates))) * limit;
+    }
+
+    return smooth_iterations(get_escape(in.xy));
+}
@@ -0,0 +1,187 @@
+use iced_wgpu::{
+    core::Color,
+    wgpu::{self, ShaderStages},
+};
+
+use crate::{
+    camera::OrbitCamera,
+    params::{ColorParams, Iterations, TerrainParams, Viewport, Zoom},
+    pipeline,
+    scene::Scene,
+    uniform::{Bindings, RenderTexture, Uniform},
+};
+
+/// Format the escape-time shader writes the iteration counts in.
+pub const HEIGHT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Float;
+const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+// Vertices along each side of the grid, as declared by terrain.wgsl
+const GRID_SIZE: u32 = 512;
+// Room kept between the camera and the sphere around the grid
+const CLEARANCE: f32 = 0.2;
+const MAX_DISTANCE: f32 = 20.;
+// Starting camera, looking down at the view from the bottom edge
+const DEFAULT_CAMERA: (f32, f32, f32) = (0., 0.7, 3.5);
+
+/// Renderer of the terrain view. The escape-time shader draws the smooth
+/// iteration count of every pixel into a height texture, which displaces a
+/// grid over the view drawn in perspective from an orbiting camera.
+pub struct Terrain {
+    pipeline: wgpu::RenderPipeline,
+    params: Uniform<TerrainParams>,
+    heights: RenderTexture,
+    depth: RenderTexture,
+    bindings: Bindings,
+    camera: OrbitCamera,
+    // Width of the view over its height, which the grid spans
+    ratio: f32,
+}
+
+impl Terrain {
+    pub fn new(
+        device: &wgpu::Device,
+        texture_format: wgpu::TextureFormat,
+        viewport: &Uniform<Viewport>,
+        color_params: &Uniform<ColorParams>,
+        iterations: &Uniform<Iterations>,
+        params: TerrainParams,
+    ) -> Self {
+        let params = Uniform::new("TerrainParams", params, device);
+        let heights = RenderTexture::new("Heights", viewport.get_size(), HEIGHT_FORMAT, device);
+        let depth = RenderTexture::new("Depth", viewport.get_size(), DEPTH_FORMAT, device);
+        let bindings = Bindings::new(
+            "Terrain",
+            &[viewport, color_params, iterations, &params, &heights],
+            ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+            device,
+        );
+
+        let shader =
+            pipeline::shader_module(device, "Terrain Shader", include_str!("terrain.wgsl"));
+        let pipeline_layout = pipeline::pipeline_layout(device, &[&bindings]);
+        let pipeline = pipeline::render_pipeline(
+            device,
+            "Terrain",
+            &pipeline_layout,
+            &shader,
+            ("vs_grid", "fs_main"),
+            pipeline::replace_target(texture_format),
+            Some(wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+        );
+
+        let camera = OrbitCamera::new(
+            DEFAULT_CAMERA,
+            (
+                min_distance(viewport.ratio, params.get_height_scale()),
+                MAX_DISTANCE,
+            ),
+        );
+
+        Self {
+            pipeline,
+            params,
+            heights,
+            depth,
+            bindings,
+            camera,
+            ratio: viewport.ratio,
+        }
+    }
+
+    /// Starts height and depth textures of the new size, bound with the
+    /// resized viewport.
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        viewport: &Uniform<Viewport>,
+        color_params: &Uniform<ColorParams>,
+        iterations: &Uniform<Iterations>,
+    ) {
+        self.heights = RenderTexture::new("Heights", viewport.get_size(), HEIGHT_FORMAT, device);
+        self.depth = RenderTexture::new("Depth", viewport.get_size(), DEPTH_FORMAT, device);
+        self.bindings.rebind(
+            "Terrain",
+            &[
+                viewport,
+                color_params,
+                iterations,
+                &self.params,
+                &self.heights,
+            ],
+            device,
+        );
+        self.ratio = viewport.ratio;
+        self.camera
+            .set_min_distance(min_distance(self.ratio, self.params.get_height_scale()));
+    }
+
+    pub fn get_params(&self) -> TerrainParams {
+        self.params.set_camera((0., 0., 0.))
+    }
+
+    pub fn set_params(&mut self, params: TerrainParams) {
+        self.params.set(params);
+        self.camera
+            .set_min_distance(min_distance(self.ratio, params.get_height_scale()));
+    }
+
+    /// Texture the escape-time passes draw the iteration counts into.
+    pub fn get_heights(&self) -> &wgpu::TextureView {
+        self.heights.get_view()
+    }
+
+    /// Turns the camera around the view by the `motion` of the cursor, in
+    /// pixels.
+    pub fn orbit(&mut self, motion: (f32, f32)) {
+        self.camera.orbit(motion);
+    }
+
+    /// Moves the camera toward or away from the view.
+    pub fn dolly(&mut self, zoom: Zoom) {
+        self.camera.dolly(zoom);
+    }
+
+    /// Draws the grid displaced by the heights, which must already be drawn
+    /// for this frame, along with the uniforms it shares with the
+    /// escape-time shader.
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        target: &wgpu::TextureView,
+        background_color: Color,
+    ) {
+        self.params.set(self.params.set_camera(self.camera.get()));
+        self.params.upload(queue);
+
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        {
+            let mut render_pass = Scene::begin_depth_render_pass(
+                target,
+                &mut encoder,
+                Some(background_color),
+                Some(self.depth.get_view()),
+            );
+
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_bind_group(0, self.bindings.get_bind_group(), &[]);
+            render_pass.draw(0..(GRID_SIZE - 1) * (GRID_SIZE - 1) * 6, 0..1);
+        }
+
+        queue.submit([encoder.finish()]);
+    }
+}
+
+/// Closest the camera gets to the center of the grid, outside the sphere
+/// around it. The grid spans `ratio` and 1 from the center across and down
+/// the view, and rises up to `height_scale`.
+fn min_distance(ratio: f32, height_scale: f32) -> f32 {
+    ratio.hypot(1.).hypot(height_scale) + CLEARANCE
+}
@@ -0,0 +1,164 @@
+// Terrain view: the smooth iteration counts of the escape-time view, drawn
+// by fs_height into a texture, as the height of a grid over the view. Lit by
+// a light over the camera, with optional contour lines at even heights.
+
+struct Iterations {
+    value: i32,
+};
+
+struct TerrainParams {
+    height_scale: f32,
+    contours: u32,
+    yaw: f32,
+    pitch: f32,
+    distance: f32,
+};
+
+@group(0) @binding(0)
+var<uniform> viewport: Viewport;
+
+@group(0) @binding(1)
+var<uniform> color_params: ColorParams;
+
+@group(0) @binding(2)
+var<uniform> max_iterations: Iterations;
+
+@group(0) @binding(3)
+var<uniform> params: TerrainParams;
+
+// Smooth iteration count of every pixel of the view
+@group(0) @binding(4)
+var heights: texture_2d<f32>;
+
+// Vertices along each side of the grid, as in terrain.rs
+const GRID_SIZE: u32 = 512u;
+// Distance from the eye to the screen, like the Mandelbulb's
+const FOCAL_LENGTH: f32 = 1.5;
+const NEAR: f32 = 0.01;
+const FAR: f32 = 100.0;
+const AMBIENT: f32 = 0.2;
+// Darkness of the contour lines, and their width in pixels
+const CONTOUR_SHADE: f32 = 0.6;
+const CONTOUR_WIDTH: f32 = 1.0;
+
+struct Camera {
+    eye: vec3<f32>,
+    forward: vec3<f32>,
+    right: vec3<f32>,
+    up: vec3<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) normal: vec3<f32>,
+    // Iteration count relative to the limit, which colours the terrain
+    @location(1) value: f32,
+    // Height in [0, 1], which the contour lines follow
+    @location(2) height: f32,
+};
+
+// Camera orbiting the origin, y up
+fn camera() -> Camera {
+    let eye = params.distance * vec3(
+        cos(params.pitch) * sin(params.yaw),
+        sin(params.pitch),
+        cos(params.pitch) * cos(params.yaw),
+    );
+    let forward = normalize(-eye);
+    let right = normalize(cross(forward, vec3(0.0, 1.0, 0.0)));
+
+    return Camera(eye, forward, right, cross(right, forward));
+}
+
+// Grows with the logarithm of the count, so the slopes far from the set,
+// which only take a few iterations, don't flatten next to the set
+fn height(count: f32) -> f32 {
+    let limit = f32(max_iterations.value);
+
+    return log(1.0 + min(count, limit)) / log(1.0 + limit);
+}
+
+// Count at a grid vertex, from the pixel under it
+fn count_at(cell: vec2<i32>) -> f32 {
+    let size = vec2<i32>(textureDimensions(heights));
+    let grid = clamp(cell, vec2(0), vec2(i32(GRID_SIZE) - 1));
+    let pixel = grid * (size - 1) / (i32(GRID_SIZE) - 1);
+
+    return textureLoad(heights, pixel, 0).r;
+}
+
+// The grid spans the view, its x across the width and its z down the
+// height, so the top of the view is the far side from the starting camera
+fn grid_position(cell: vec2<i32>) -> vec3<f32> {
+    let uv = vec2<f32>(cell) / f32(GRID_SIZE - 1u) * 2.0 - 1.0;
+
+    return vec3(uv.x * viewport.ratio, height(count_at(cell)) * params.height_scale, uv.y);
+}
+
+// Two triangles per cell of the grid, six vertices each
+@vertex
+fn vs_grid(@builtin(vertex_index) vertex_idx: u32) -> VertexOutput {
+    let quad = vertex_idx / 6u;
+    var corner = vec2(0, 0);
+    switch vertex_idx % 6u {
+        case 1u, 4u: {
+            corner = vec2(1, 0);
+        }
+        case 2u, 3u: {
+            corner = vec2(0, 1);
+        }
+        case 5u: {
+            corner = vec2(1, 1);
+        }
+        default: {}
+    }
+    let cell = vec2(i32(quad % (GRID_SIZE - 1u)), i32(quad / (GRID_SIZE - 1u))) + corner;
+
+    let position = grid_position(cell);
+    let across = grid_position(cell + vec2(1, 0)) - grid_position(cell - vec2(1, 0));
+    let down = grid_position(cell + vec2(0, 1)) - grid_position(cell - vec2(0, 1));
+
+    // Perspective projection onto the screen at the focal length, with the
+    // depth growing from the near to the far plane
+    let camera = camera();
+    let relative = position - camera.eye;
+    let depth = dot(relative, camera.forward);
+    let clip = vec4(
+        dot(relative, camera.right) * FOCAL_LENGTH / viewport.ratio,
+        dot(relative, camera.up) * FOCAL_LENGTH,
+        (depth - NEAR) * FAR / (FAR - NEAR),
+        depth,
+    );
+
+    let count = count_at(cell);
+
+    return VertexOutput(
+        clip,
+        normalize(cross(down, across)),
+        count / f32(max_iterations.value),
+        height(count),
+    );
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let camera = camera();
+    let light = normalize(camera.up - 0.5 * camera.right - 0.3 * camera.forward);
+    // Lit from whichever side faces the camera
+    let normal = normalize(in.normal) * select(1.0, -1.0, dot(in.normal, camera.forward) > 0.0);
+    let diffuse = max(dot(normal, light), 0.0);
+
+    var color = hsl_color(in.value, color_params) * (AMBIENT + (1.0 - AMBIENT) * diffuse);
+
+    // Lines at the contours levels evenly between the ground and the set,
+    // which are left out. The distance to the nearest one in pixels is taken
+    // outside the branch for the derivatives to be defined.
+    let level = in.height * f32(params.contours + 1u);
+    let offset = abs(fract(level + 0.5) - 0.5) / max(fwidth(level), 1e-6);
+    if level > 0.5 && level < f32(params.contours) + 0.5 {
+        let line = 1.0 - smoothstep(0.0, CONTOUR_WIDTH, offset);
+        color *= 1.0 - CONTOUR_SHADE * line;
+    }
+
+    return vec4(color, 1.0);
+}
@@ -1,63 +1,63 @@
-use std::ops::Deref;
+use std::{
+    marker::PhantomData,
+    ops::Deref,
+    sync::{Arc, OnceLock},
+};
 
 use bytemuck::Pod;
 use iced_wgpu::wgpu::{self, util::DeviceExt};
 
-pub struct Uniform<T> {
-    buffer: wgpu::Buffer,
+/// A buffer that can be attached to a [`Bindings`] group.
+pub trait Binding {
+    fn binding_type(&self) -> wgpu::BindingType;
+
+    fn resource(&self) -> wgpu::BindingResource<'_>;
+}
+
+/// Several buffers sharing one bind group, each bound at its position in the
+/// list. Grouping keeps us under the bind group limit of the default device.
+pub struct Bindings {
     bind_group_layout: wgpu::BindGroupLayout,
     bind_group: wgpu::BindGroup,
-
-    value: T,
-    should_update: bool,
 }
 
-impl<T: Pod> Uniform<T> {
+impl Bindings {
     pub fn new(
         name: &str,
-        value: T,
+        bindings: &[&dyn Binding],
         visibility: wgpu::ShaderStages,
         device: &wgpu::Device,
     ) -> Self {
-        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some(&format!("{name} Buffer")),
-            contents: bytemuck::cast_slice(&[value]),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
-
-        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
+        let layout_entries = bindings
+            .iter()
+            .enumerate()
+            .map(|(index, binding)| wgpu::BindGroupLayoutEntry {
+                binding: index as u32,
                 visibility,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
+                ty: binding.binding_type(),
                 count: None,
-            }],
+            })
+            .collect::<Vec<_>>();
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &layout_entries,
             label: Some(&format!("{name}_bind_group_layout")),
         });
 
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: buffer.as_entire_binding(),
-            }],
-            label: Some(&format!("{name}_bind_group")),
-        });
+        let bind_group = create_bind_group(name, &bind_group_layout, bindings, device);
 
         Self {
-            bind_group,
             bind_group_layout,
-            buffer,
-
-            value,
-            should_update: false,
+            bind_group,
         }
     }
 
+    /// Points the group at new buffers, keeping the layout pipelines were
+    /// built with.
+    pub fn rebind(&mut self, name: &str, bindings: &[&dyn Binding], device: &wgpu::Device) {
+        self.bind_group = create_bind_group(name, &self.bind_group_layout, bindings, device);
+    }
+
     pub fn get_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
         &self.bind_group_layout
     }
@@ -65,6 +65,52 @@ impl<T: Pod> Uniform<T> {
     pub fn get_bind_group(&self) -> &wgpu::BindGroup {
         &self.bind_group
     }
+}
+
+fn create_bind_group(
+    name: &str,
+    layout: &wgpu::BindGroupLayout,
+    bindings: &[&dyn Binding],
+    device: &wgpu::Device,
+) -> wgpu::BindGroup {
+    let entries = bindings
+        .iter()
+        .enumerate()
+        .map(|(index, binding)| wgpu::BindGroupEntry {
+            binding: index as u32,
+            resource: binding.resource(),
+        })
+        .collect::<Vec<_>>();
+
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &entries,
+        label: Some(&format!("{name}_bind_group")),
+    })
+}
+
+pub struct Uniform<T> {
+    buffer: wgpu::Buffer,
+
+    value: T,
+    should_update: bool,
+}
+
+impl<T: Pod> Uniform<T> {
+    pub fn new(name: &str, value: T, device: &wgpu::Device) -> Self {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{name} Buffer")),
+            contents: bytemuck::cast_slice(&[value]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        Self {
+            buffer,
+
+            value,
+            should_update: false,
+        }
+    }
 
     pub fn set(&mut self, value: T) {
         self.value = value;
@@ -79,6 +125,20 @@ impl<T: Pod> Uniform<T> {
     }
 }
 
+impl<T> Binding for Uniform<T> {
+    fn binding_type(&self) -> wgpu::BindingType {
+        wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        }
+    }
+
+    fn resource(&self) -> wgpu::BindingResource<'_> {
+        self.buffer.as_entire_binding()
+    }
+}
+
 impl<T> Deref for Uniform<T> {
     type Target = T;
 
@@ -86,3 +146,276 @@ impl<T> Deref for Uniform<T> {
         &self.value
     }
 }
+
+/// A fixed capacity array buffer, read by the shader as a runtime-sized
+/// `array<T>`.
+pub struct Storage<T> {
+    buffer: wgpu::Buffer,
+    capacity: usize,
+
+    values: Vec<T>,
+    should_update: bool,
+}
+
+impl<T: Pod> Storage<T> {
+    pub fn new(name: &str, capacity: usize, device: &wgpu::Device) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("{name} Buffer")),
+            size: (capacity.max(1) * std::mem::size_of::<T>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            buffer,
+            capacity,
+
+            values: Vec::new(),
+            should_update: false,
+        }
+    }
+
+    pub fn set(&mut self, values: &[T]) {
+        self.values.clear();
+        self.values
+            .extend_from_slice(&values[..values.len().min(self.capacity)]);
+        self.should_update = true;
+    }
+
+    pub fn upload(&mut self, queue: &wgpu::Queue) {
+        if self.should_update {
+            if !self.values.is_empty() {
+                queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&self.values));
+            }
+            self.should_update = false;
+        }
+    }
+}
+
+impl<T> Binding for Storage<T> {
+    fn binding_type(&self) -> wgpu::BindingType {
+        wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: true },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        }
+    }
+
+    fn resource(&self) -> wgpu::BindingResource<'_> {
+        self.buffer.as_entire_binding()
+    }
+}
+
+impl<T> Deref for Storage<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &Self::Target {
+        &self.values
+    }
+}
+
+/// An `array<T>` the shader writes to, with a staging buffer to copy it back
+/// to the CPU. The copy is mapped asynchronously, so what the shader wrote is
+/// only read in a later frame, without ever waiting on the GPU.
+pub struct ShaderOutput<T> {
+    buffer: wgpu::Buffer,
+    readback: wgpu::Buffer,
+    len: usize,
+    // Whether the copy requested last could be mapped, once it is done. None
+    // when no copy is pending.
+    mapped: Option<Arc<OnceLock<bool>>>,
+
+    _marker: PhantomData<T>,
+}
+
+impl<T: Pod> ShaderOutput<T> {
+    pub fn new(name: &str, len: usize, device: &wgpu::Device) -> Self {
+        let size = (len.max(1) * std::mem::size_of::<T>()) as wgpu::BufferAddress;
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("{name} Buffer")),
+            size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("{name} Readback Buffer")),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            buffer,
+            readback,
+            len,
+            mapped: None,
+
+            _marker: PhantomData,
+        }
+    }
+
+    /// Copies what the work submitted so far wrote, to be read back with
+    /// [`ShaderOutput::try_read`] once the GPU is done. Does nothing while an
+    /// earlier copy is still pending.
+    pub fn request(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if self.mapped.is_some() {
+            return;
+        }
+
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_buffer_to_buffer(&self.buffer, 0, &self.readback, 0, None);
+        queue.submit([encoder.finish()]);
+
+        let mapped = Arc::new(OnceLock::new());
+        let callback_mapped = Arc::clone(&mapped);
+        self.readback
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = callback_mapped.set(result.is_ok());
+            });
+        self.mapped = Some(mapped);
+    }
+
+    /// What the shader wrote when the pending copy was requested, once it is
+    /// mapped. Never waits: the browser maps it on its own on the web, and
+    /// the device is only polled here natively.
+    pub fn try_read(&mut self, device: &wgpu::Device) -> Option<Vec<T>> {
+        let mapped = self.mapped.as_ref()?;
+        let _ = device.poll(wgpu::PollType::Poll);
+        match mapped.get() {
+            None => return None,
+            Some(false) => {
+                self.mapped = None;
+                return None;
+            }
+            Some(true) => {}
+        }
+
+        let slice = self.readback.slice(..);
+        let values = bytemuck::cast_slice::<u8, T>(&slice.get_mapped_range())[..self.len].to_vec();
+        self.readback.unmap();
+        self.mapped = None;
+
+        Some(values)
+    }
+
+    /// Drops the pending copy, which no longer matches what the shader
+    /// writes. Unmapping aborts the mapping if it isn't done yet.
+    pub fn discard(&mut self) {
+        if self.mapped.take().is_some() {
+            self.readback.unmap();
+        }
+    }
+}
+
+impl<T> Binding for ShaderOutput<T> {
+    fn binding_type(&self) -> wgpu::BindingType {
+        wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: false },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        }
+    }
+
+    fn resource(&self) -> wgpu::BindingResource<'_> {
+        self.buffer.as_entire_binding()
+    }
+}
+
+/// An `array<T>` only the shader reads and writes, like a histogram built up
+/// over several passes, cleared from the GPU.
+pub struct ShaderBuffer<T> {
+    buffer: wgpu::Buffer,
+
+    _marker: PhantomData<T>,
+}
+
+impl<T: Pod> ShaderBuffer<T> {
+    pub fn new(name: &str, len: usize, device: &wgpu::Device) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("{name} Buffer")),
+            size: (len.max(1) * std::mem::size_of::<T>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            buffer,
+
+            _marker: PhantomData,
+        }
+    }
+
+    /// Zeroes the buffer before the work recorded next in `encoder`.
+    pub fn clear(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.clear_buffer(&self.buffer, 0, None);
+    }
+}
+
+impl<T> Binding for ShaderBuffer<T> {
+    fn binding_type(&self) -> wgpu::BindingType {
+        wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: false },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        }
+    }
+
+    fn resource(&self) -> wgpu::BindingResource<'_> {
+        self.buffer.as_entire_binding()
+    }
+}
+
+/// A texture one pipeline renders into, read by the shader of another with
+/// `textureLoad`.
+pub struct RenderTexture {
+    view: wgpu::TextureView,
+}
+
+impl RenderTexture {
+    pub fn new(
+        name: &str,
+        (width, height): (usize, usize),
+        format: wgpu::TextureFormat,
+        device: &wgpu::Device,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(&format!("{name} Texture")),
+            size: wgpu::Extent3d {
+                width: width.max(1) as u32,
+                height: height.max(1) as u32,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        Self {
+            view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+        }
+    }
+
+    pub fn get_view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+}
+
+impl Binding for RenderTexture {
+    fn binding_type(&self) -> wgpu::BindingType {
+        wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        }
+    }
+
+    fn resource(&self) -> wgpu::BindingResource<'_> {
+        wgpu::BindingResource::TextureView(&self.view)
+    }
+}